@@ -0,0 +1,531 @@
+//! Tags remembered between runs, keyed by note modification time and size.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use log::warn;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{load_tags_keyed, relative_path, Tags, YamlError};
+
+/// Where `--cache` keeps the caches of every vault when given no path.
+pub fn default_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("obsidian-get-tags").join("cache.json"))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    mtime: SystemTime,
+    /// Length of the note, which catches edits within the mtime resolution
+    #[serde(default)]
+    size: u64,
+    /// Whether the tags were read with `--legacy-props`
+    #[serde(default)]
+    legacy_props: bool,
+    /// The keys read besides `tags` and `tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags_keys: Vec<String>,
+    tags: Tags,
+    /// The tags of the body, when it was scanned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline: Option<InlineEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct InlineEntry {
+    scan: InlineScan,
+    tags: Tags,
+}
+
+/// How the body of a note is scanned for inline tags: a cached result is only
+/// reused by a scan done the same way.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InlineScan {
+    /// The tag pattern, see [`TagPattern`](crate::inline_tags::TagPattern)
+    pub pattern: String,
+    pub include_code: bool,
+    pub scan_drawings: bool,
+}
+
+/// The caches of the vaults being scanned, all kept
+/// together in a single file or in a SQLite index.
+#[derive(Debug)]
+pub struct TagCache {
+    vaults: Vec<VaultCache>,
+    store: Store,
+}
+
+#[derive(Debug)]
+enum Store {
+    /// One cache file for every vault
+    File {
+        path: PathBuf,
+        /// Caches of the vaults not scanned this time, kept as they are
+        others: HashMap<PathBuf, HashMap<PathBuf, CacheEntry>>,
+    },
+    /// A SQLite database, updated in place on save
+    Index { path: PathBuf },
+}
+
+impl TagCache {
+    /// Reads the caches of the vaults at `roots` from the single file at
+    /// `path`, which may also hold those of other vaults. Vaults are told
+    /// apart by their canonical path.
+    pub fn load_from(path: &Path, roots: &[PathBuf]) -> Self {
+        let mut others: HashMap<PathBuf, HashMap<PathBuf, CacheEntry>> = read_cache(path);
+        let vaults = roots
+            .iter()
+            .map(|root| {
+                let stored = others.remove(&canonical(root)).unwrap_or_default();
+                VaultCache::new(root, stored)
+            })
+            .collect();
+        TagCache {
+            vaults,
+            store: Store::File {
+                path: path.to_path_buf(),
+                others,
+            },
+        }
+    }
+
+    /// Reads the caches of the vaults at `roots` from the SQLite index at
+    /// `path`, where each note is a row keyed by its vault and its path in
+    /// the vault. With `rebuild` the stored rows are not reused, so every
+    /// note scanned is parsed again and its row rewritten on save.
+    pub fn load_index(path: &Path, roots: &[PathBuf], rebuild: bool) -> Self {
+        let vaults = roots
+            .iter()
+            .map(|root| {
+                let stored = read_index(path, root).unwrap_or_else(|e| {
+                    warn!("{}: ignoring the index: {}", path.display(), e);
+                    HashMap::new()
+                });
+                VaultCache {
+                    reuse: !rebuild,
+                    ..VaultCache::new(root, stored)
+                }
+            })
+            .collect();
+        TagCache {
+            vaults,
+            store: Store::Index {
+                path: path.to_path_buf(),
+            },
+        }
+    }
+
+    /// [`load_tags_keyed`], skipped when `path` hasn't changed since it was
+    /// cached. Notes outside of the vaults are always parsed. Failures are not
+    /// cached, so they are reported on every run.
+    pub fn load_tags(
+        &self,
+        path: &Path,
+        legacy_props: bool,
+        tags_keys: &[String],
+    ) -> Result<Tags, YamlError> {
+        match self.vault_of(path) {
+            Some(vault) => vault.load_tags(path, legacy_props, tags_keys),
+            None => load_tags_keyed(path, legacy_props, tags_keys),
+        }
+    }
+
+    /// The front matter and inline tags of `path` as `scan_note` reads them,
+    /// skipped when the note hasn't changed since it was cached with the same
+    /// settings. Like in [`TagCache::load_tags`], failures are not cached.
+    pub fn scan_note(
+        &self,
+        path: &Path,
+        legacy_props: bool,
+        tags_keys: &[String],
+        scan: &InlineScan,
+        scan_note: impl FnOnce() -> (Result<Tags, YamlError>, Tags),
+    ) -> (Result<Tags, YamlError>, Tags) {
+        match self.vault_of(path) {
+            Some(vault) => vault.scan_note(path, legacy_props, tags_keys, scan, scan_note),
+            None => scan_note(),
+        }
+    }
+
+    /// The innermost vault holding `path`, when one is nested in another.
+    fn vault_of(&self, path: &Path) -> Option<&VaultCache> {
+        self.vaults
+            .iter()
+            .filter(|vault| path.starts_with(&vault.root))
+            .max_by_key(|vault| vault.root.components().count())
+    }
+
+    /// Writes the notes seen during this run back to the cache. The entries
+    /// of notes that were not scanned are kept until the notes are deleted,
+    /// so that a filtered run doesn't empty the cache.
+    pub fn save(self) -> std::io::Result<()> {
+        match self.store {
+            Store::File { path, mut others } => {
+                for vault in self.vaults {
+                    others.insert(canonical(&vault.root), vault.into_entries().1);
+                }
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                write_cache(&path, &others)
+            }
+            Store::Index { path } => {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                write_index(&path, self.vaults)
+                    .map_err(|e| std::io::Error::other(format!("{}: {}", path.display(), e)))
+            }
+        }
+    }
+}
+
+fn canonical(root: &Path) -> PathBuf {
+    root.canonicalize().unwrap_or_else(|_| root.to_path_buf())
+}
+
+/// A missing or unreadable cache is an empty one.
+fn read_cache<T: DeserializeOwned + Default>(path: &Path) -> T {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|e| {
+            warn!("{}: ignoring the cache: {}", path.display(), e);
+            T::default()
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => T::default(),
+        Err(e) => {
+            warn!("{}: ignoring the cache: {}", path.display(), e);
+            T::default()
+        }
+    }
+}
+
+fn write_cache(path: &Path, cache: &impl Serialize) -> std::io::Result<()> {
+    // write next to the cache and rename, so an interrupted run can't
+    // leave half a file behind
+    let partial = path.with_extension("partial");
+    serde_json::to_writer(BufWriter::new(File::create(&partial)?), cache)?;
+    fs::rename(partial, path)
+}
+
+const INDEX_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS notes (
+    vault TEXT NOT NULL,
+    path TEXT NOT NULL,
+    mtime INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    legacy_props INTEGER NOT NULL,
+    tags_keys TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    inline TEXT,
+    PRIMARY KEY (vault, path)
+)";
+
+/// The rows of the vault at `root`; a missing index is an empty one.
+fn read_index(path: &Path, root: &Path) -> rusqlite::Result<HashMap<PathBuf, CacheEntry>> {
+    let connection = match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(connection) => connection,
+        Err(_) if !path.exists() => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let table = connection
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'notes'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    if table.is_none() {
+        return Ok(HashMap::new());
+    }
+    let mut select = connection.prepare(
+        "SELECT path, mtime, size, legacy_props, tags_keys, tags, inline FROM notes
+         WHERE vault = ?1",
+    )?;
+    let vault = canonical(root);
+    let rows = select.query_map(params![vault.to_string_lossy()], |row| {
+        let path: String = row.get(0)?;
+        // SQLite integers are signed
+        let mtime: i64 = row.get(1)?;
+        let size: i64 = row.get(2)?;
+        let tags_keys: String = row.get(4)?;
+        let tags: String = row.get(5)?;
+        let inline: Option<String> = row.get(6)?;
+        Ok((
+            PathBuf::from(path),
+            CacheEntry {
+                mtime: SystemTime::UNIX_EPOCH + Duration::from_nanos(mtime as u64),
+                size: size as u64,
+                legacy_props: row.get(3)?,
+                // a row that doesn't parse is a note to scan again
+                tags_keys: serde_json::from_str(&tags_keys).unwrap_or_default(),
+                tags: serde_json::from_str(&tags).unwrap_or_default(),
+                inline: inline.and_then(|inline| serde_json::from_str(&inline).ok()),
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Writes the notes seen in `vaults` to the index at `path`: only the rows
+/// of the notes that changed are written, and those of the notes that are
+/// gone are deleted.
+fn write_index(path: &Path, vaults: Vec<VaultCache>) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+    connection.execute(INDEX_SCHEMA, [])?;
+    let transaction = connection.transaction()?;
+    {
+        let mut delete = transaction.prepare("DELETE FROM notes WHERE vault = ?1 AND path = ?2")?;
+        let mut insert = transaction.prepare(
+            "INSERT OR REPLACE INTO notes
+             (vault, path, mtime, size, legacy_props, tags_keys, tags, inline)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for vault in vaults {
+            let root = canonical(&vault.root);
+            let root = root.to_string_lossy();
+            let (stored, entries) = vault.into_entries();
+            for key in stored.keys().filter(|key| !entries.contains_key(*key)) {
+                delete.execute(params![root, key.to_string_lossy()])?;
+            }
+            for (key, entry) in &entries {
+                if stored.get(key) == Some(entry) {
+                    continue;
+                }
+                let mtime = entry
+                    .mtime
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |since| since.as_nanos() as i64);
+                let inline = entry
+                    .inline
+                    .as_ref()
+                    .map(|inline| serde_json::to_string(inline).expect("entries serialize"));
+                insert.execute(params![
+                    root,
+                    key.to_string_lossy(),
+                    mtime,
+                    entry.size as i64,
+                    entry.legacy_props,
+                    serde_json::to_string(&entry.tags_keys).expect("entries serialize"),
+                    serde_json::to_string(&entry.tags).expect("entries serialize"),
+                    inline,
+                ])?;
+            }
+        }
+    }
+    transaction.commit()
+}
+
+/// The cache of a vault: the entries read at startup, and those of the notes
+/// seen during this run, which replace them on save.
+#[derive(Debug, Default)]
+struct VaultCache {
+    root: PathBuf,
+    stored: HashMap<PathBuf, CacheEntry>,
+    /// Whether the stored entries may stand in for the notes
+    reuse: bool,
+    seen: Mutex<HashMap<PathBuf, CacheEntry>>,
+}
+
+impl VaultCache {
+    fn new(root: &Path, stored: HashMap<PathBuf, CacheEntry>) -> Self {
+        VaultCache {
+            root: root.to_path_buf(),
+            stored,
+            reuse: true,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// The stored entry of `key`, if it is still that of a note of `mtime`
+    /// and `size` read with `legacy_props` and `tags_keys`.
+    fn fresh(
+        &self,
+        key: &Path,
+        mtime: SystemTime,
+        size: u64,
+        legacy_props: bool,
+        tags_keys: &[String],
+    ) -> Option<&CacheEntry> {
+        self.stored.get(key).filter(|entry| {
+            self.reuse
+                && entry.mtime == mtime
+                && entry.size == size
+                && entry.legacy_props == legacy_props
+                && entry.tags_keys == tags_keys
+        })
+    }
+
+    fn load_tags(
+        &self,
+        path: &Path,
+        legacy_props: bool,
+        tags_keys: &[String],
+    ) -> Result<Tags, YamlError> {
+        let metadata = fs::metadata(path)?;
+        let (mtime, size) = (metadata.modified()?, metadata.len());
+        let key = relative_path(&self.root, path);
+        let fresh = self.fresh(&key, mtime, size, legacy_props, tags_keys);
+        let (tags, inline) = match fresh {
+            Some(entry) => (entry.tags.clone(), entry.inline.clone()),
+            None => (load_tags_keyed(path, legacy_props, tags_keys)?, None),
+        };
+        let entry = CacheEntry {
+            mtime,
+            size,
+            legacy_props,
+            tags_keys: tags_keys.to_vec(),
+            tags: tags.clone(),
+            inline,
+        };
+        self.seen.lock().expect("cache poisoned").insert(key, entry);
+        Ok(tags)
+    }
+
+    fn scan_note(
+        &self,
+        path: &Path,
+        legacy_props: bool,
+        tags_keys: &[String],
+        scan: &InlineScan,
+        scan_note: impl FnOnce() -> (Result<Tags, YamlError>, Tags),
+    ) -> (Result<Tags, YamlError>, Tags) {
+        let metadata = match fs::metadata(path).and_then(|m| Ok((m.modified()?, m.len()))) {
+            Ok(metadata) => metadata,
+            Err(_) => return scan_note(),
+        };
+        let (mtime, size) = metadata;
+        let key = relative_path(&self.root, path);
+        let cached = self
+            .fresh(&key, mtime, size, legacy_props, tags_keys)
+            .and_then(|entry| match &entry.inline {
+                Some(inline) if inline.scan == *scan => {
+                    Some((entry.tags.clone(), inline.tags.clone()))
+                }
+                _ => None,
+            });
+        let (tags, inline) = match cached {
+            Some(found) => found,
+            None => match scan_note() {
+                (Ok(tags), inline) => (tags, inline),
+                failed => return failed,
+            },
+        };
+        let entry = CacheEntry {
+            mtime,
+            size,
+            legacy_props,
+            tags_keys: tags_keys.to_vec(),
+            tags: tags.clone(),
+            inline: Some(InlineEntry {
+                scan: scan.clone(),
+                tags: inline.clone(),
+            }),
+        };
+        self.seen.lock().expect("cache poisoned").insert(key, entry);
+        (Ok(tags), inline)
+    }
+
+    /// The stored entries, and those to save: the notes seen during this run
+    /// and the stored ones that were not scanned but still exist.
+    fn into_entries(self) -> (HashMap<PathBuf, CacheEntry>, HashMap<PathBuf, CacheEntry>) {
+        let mut entries = self.seen.into_inner().expect("cache poisoned");
+        for (key, entry) in &self.stored {
+            if !entries.contains_key(key) && self.root.join(key).is_file() {
+                entries.insert(key.clone(), entry.clone());
+            }
+        }
+        (self.stored, entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_common::fixture_vault;
+
+    #[test]
+    fn reuses_entries_until_the_note_changes() {
+        let dir = fixture_vault(&[("vault/note.md", "---\ntags: [before]\n---\n")]);
+        let root = dir.join("vault");
+        let note = root.join("note.md");
+        let file = dir.join("cache.json");
+
+        let cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        assert_eq!(cache.load_tags(&note, false, &[]).unwrap(), ["before"]);
+        cache.save().unwrap();
+
+        // a cached entry wins as long as the modification time matches
+        let mut cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        let key = PathBuf::from("note.md");
+        cache.vaults[0].stored.get_mut(&key).unwrap().tags = vec!["cached".to_string()];
+        assert_eq!(cache.load_tags(&note, false, &[]).unwrap(), ["cached"]);
+
+        cache.vaults[0].stored.get_mut(&key).unwrap().mtime = SystemTime::UNIX_EPOCH;
+        assert_eq!(cache.load_tags(&note, false, &[]).unwrap(), ["before"]);
+    }
+
+    #[test]
+    fn unscanned_notes_are_kept_until_deleted() {
+        let dir = fixture_vault(&[
+            ("vault/kept.md", "---\ntags: [kept]\n---\n"),
+            ("vault/gone.md", "---\ntags: [gone]\n---\n"),
+        ]);
+        let root = dir.join("vault");
+        let (kept, gone) = (root.join("kept.md"), root.join("gone.md"));
+        let file = dir.join("cache.json");
+
+        let cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        cache.load_tags(&kept, false, &[]).unwrap();
+        cache.load_tags(&gone, false, &[]).unwrap();
+        cache.save().unwrap();
+        fs::remove_file(&gone).unwrap();
+
+        // a filtered run scanning neither note
+        TagCache::load_from(&file, std::slice::from_ref(&root))
+            .save()
+            .unwrap();
+
+        let cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        let stored = &cache.vaults[0].stored;
+        assert!(stored.contains_key(Path::new("kept.md")));
+        assert!(!stored.contains_key(Path::new("gone.md")));
+    }
+
+    #[test]
+    fn shared_file_keeps_inline_tags_per_scan() {
+        let dir = fixture_vault(&[("vault/note.md", "#body\n")]);
+        let root = dir.join("vault");
+        let note = root.join("note.md");
+        let file = dir.join("cache/cache.json");
+        let scan = InlineScan {
+            pattern: "x".to_string(),
+            include_code: false,
+            scan_drawings: false,
+        };
+        let scanned = || (Ok(vec!["front".to_string()]), vec!["#body".to_string()]);
+        let unreachable = || -> (Result<Tags, YamlError>, Tags) { panic!("scanned again") };
+
+        let cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        let (front, _) = cache.scan_note(&note, false, &[], &scan, scanned);
+        assert!(front.is_ok());
+        cache.save().unwrap();
+
+        let cache = TagCache::load_from(&file, std::slice::from_ref(&root));
+        let (front, inline) = cache.scan_note(&note, false, &[], &scan, unreachable);
+        assert_eq!(front.unwrap(), ["front"]);
+        assert_eq!(inline, ["#body"]);
+        // another pattern is another scan
+        let other = InlineScan {
+            include_code: true,
+            ..scan.clone()
+        };
+        let (_, inline) =
+            cache.scan_note(&note, false, &[], &other, || (Ok(Vec::new()), Vec::new()));
+        assert!(inline.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+//! The optional TOML configuration file.
+//!
+//! ```toml
+//! vault_path = "~/Documents/vault"
+//! ```
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+    /// Vault used when neither `--path` nor `OBSIDIAN_VAULT_PATH` is given
+    pub vault_path: Option<String>,
+}
+
+impl Config {
+    /// `~/.config/obsidian-get-tags/config.toml`, or the platform equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("obsidian-get-tags").join("config.toml"))
+    }
+
+    /// Reads the config at `path`. A missing file gives the default config.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).with_context(|| path.display().to_string()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| path.display().to_string()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_common::fixture_vault;
+
+    #[test]
+    fn reads_vault_path() {
+        assert_eq!(
+            Config::parse("vault_path = \"~/vault\"\n").unwrap(),
+            Config {
+                vault_path: Some("~/vault".to_string())
+            }
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("vault_path = 1\n").is_err());
+    }
+
+    #[test]
+    fn missing_file_is_empty_config() {
+        let dir = fixture_vault(&[]);
+        let path = dir.join("config.toml");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+    }
+}
@@ -0,0 +1,239 @@
+//! Inline `#tag` extraction without spawning `rg`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use log::warn;
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::{lossy_lines, normalize_tag};
+
+/// Shape of an inline tag: `#` followed by `/`-separated segments that stop at
+/// whitespace, `#`, `|`, brackets and quotes.
+pub const TAG_BODY: &str = r#"#[^\s\#\|\(\)\[\]\"\']+(?:\/[^\s\#\|\(\)\[\]\"\']+)*"#;
+
+/// The pattern handed to `rg`. A tag must start a line or follow whitespace.
+/// Starting a line is allowed on purpose, as in Obsidian and the built-in
+/// scanner: the `(?<=\s)` that `rg` was first given missed such tags.
+pub fn rg_pattern() -> String {
+    TagPattern::default().rg_pattern()
+}
+
+/// What an inline tag looks like, [`TAG_BODY`] unless overridden.
+#[derive(Clone, Debug)]
+pub struct TagPattern {
+    body: String,
+    regex: Regex,
+}
+
+pub(crate) static DEFAULT_PATTERN: LazyLock<TagPattern> =
+    LazyLock::new(|| TagPattern::new(TAG_BODY).expect("valid tag pattern"));
+
+impl TagPattern {
+    /// A pattern matching a whole tag, `#` included. It is compiled right away
+    /// so that a bad one is reported before anything is scanned. Lookaround
+    /// is not supported, since the built-in scanner can't run it.
+    pub fn new(body: &str) -> Result<Self, regex::Error> {
+        // checked alone first, for errors to point into what the user wrote
+        Regex::new(body)?;
+        // the `regex` crate has no lookbehind, so the leading whitespace (or
+        // line start) is matched outside the capture group instead
+        let regex = Regex::new(&format!(r"(?:^|\s)({})", body))?;
+        Ok(TagPattern {
+            body: body.to_string(),
+            regex,
+        })
+    }
+
+    /// The pattern handed to `rg`, see [`rg_pattern`].
+    pub fn rg_pattern(&self) -> String {
+        format!("(?<=^|\\s)(?:{})", self.body)
+    }
+}
+
+impl Default for TagPattern {
+    fn default() -> Self {
+        DEFAULT_PATTERN.clone()
+    }
+}
+
+/// Inline tags (without their leading `#`, see [`normalize_tag`]) found in the
+/// body of a markdown file.
+///
+/// Lines inside fenced code blocks and inline code spans are ignored, and
+/// invalid UTF-8 is replaced rather than failing the file.
+pub fn extract_inline_tags(path: &Path) -> std::io::Result<Vec<String>> {
+    extract_inline_tags_with(path, &DEFAULT_PATTERN, false)
+}
+
+/// [`extract_inline_tags`], for tags shaped like `pattern`, and also within
+/// code when `include_code` is set.
+pub fn extract_inline_tags_with(
+    path: &Path,
+    pattern: &TagPattern,
+    include_code: bool,
+) -> std::io::Result<Vec<String>> {
+    scan_inline_tags_with(BufReader::new(File::open(path)?), pattern, include_code)
+}
+
+/// Scans markdown text line by line, see [`extract_inline_tags`].
+pub fn scan_inline_tags(reader: impl BufRead) -> std::io::Result<Vec<String>> {
+    scan_inline_tags_with(reader, &DEFAULT_PATTERN, false)
+}
+
+/// [`scan_inline_tags`], see [`extract_inline_tags_with`].
+pub fn scan_inline_tags_with(
+    reader: impl BufRead,
+    pattern: &TagPattern,
+    include_code: bool,
+) -> std::io::Result<Vec<String>> {
+    let mut tags = Vec::new();
+    let mut fence: Option<&'static str> = None;
+
+    for line in lossy_lines(reader) {
+        let line = line?;
+        if include_code {
+            tags.extend(line_tags(pattern, &line));
+            continue;
+        }
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            continue;
+        }
+
+        let line = strip_code_spans(&line);
+        tags.extend(line_tags(pattern, &line));
+    }
+
+    Ok(tags)
+}
+
+/// The tags of a single line, normalized.
+fn line_tags<'a>(pattern: &'a TagPattern, line: &'a str) -> impl Iterator<Item = String> + 'a {
+    pattern
+        .regex
+        .captures_iter(line)
+        .filter_map(|caps| normalize_tag(&caps[1]).map(String::from))
+}
+
+/// Replaces every `` `code` `` span with a single space.
+fn strip_code_spans(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let delimiter = &rest[start..start + ticks];
+        let after = &rest[start + ticks..];
+        match after.find(delimiter) {
+            Some(end) => {
+                out.push_str(&rest[..start]);
+                out.push(' ');
+                rest = &after[end + ticks..];
+            }
+            None => {
+                // unmatched backticks are literal text
+                out.push_str(&rest[..start + ticks]);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Every inline tag occurrence in `paths`, scanned in parallel. Unreadable
+/// files are logged and skipped.
+pub fn collect_inline_tags(paths: &[PathBuf]) -> Vec<String> {
+    collect_inline_tags_with(paths, &DEFAULT_PATTERN, false)
+}
+
+/// [`collect_inline_tags`], see [`extract_inline_tags_with`].
+pub fn collect_inline_tags_with(
+    paths: &[PathBuf],
+    pattern: &TagPattern,
+    include_code: bool,
+) -> Vec<String> {
+    paths
+        .par_iter()
+        .filter_map(
+            |path| match extract_inline_tags_with(path, pattern, include_code) {
+                Ok(tags) => Some(tags),
+                Err(e) => {
+                    warn!("{}: {}", path.display(), e);
+                    None
+                }
+            },
+        )
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(text: &str) -> Vec<String> {
+        scan_inline_tags(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn finds_plain_and_nested_tags() {
+        assert_eq!(
+            scan("#top line\nsee #project/alpha/beta, and (#skip\n"),
+            vec!["top", "project/alpha/beta,"]
+        );
+    }
+
+    #[test]
+    fn tags_may_start_a_line() {
+        assert_eq!(
+            scan(
+                "#first
+  #indented
+"
+            ),
+            vec!["first", "indented"]
+        );
+        // rg is told the same, unlike the `(?<=\s)` it was first given
+        assert!(rg_pattern().starts_with(r"(?<=^|\s)"), "{}", rg_pattern());
+    }
+
+    #[test]
+    fn tags_need_leading_whitespace() {
+        assert_eq!(scan("a#b c #d#e [[x]] #[f]\n"), vec!["d"]);
+    }
+
+    #[test]
+    fn custom_pattern() {
+        let pattern = TagPattern::new(r"#[\w+]+").unwrap();
+        let text = "#c++ and #plain, not#this\n";
+        assert_eq!(
+            scan_inline_tags_with(text.as_bytes(), &pattern, false).unwrap(),
+            vec!["c++", "plain"]
+        );
+        assert_eq!(pattern.rg_pattern(), r"(?<=^|\s)(?:#[\w+]+)");
+        assert!(TagPattern::new("#(").is_err());
+    }
+
+    #[test]
+    fn skips_code() {
+        let text = "```c\n#include <stdio.h>\n```\n~~~\n#nope\n~~~\nuse `#not` but #yes\n";
+        assert_eq!(scan(text), vec!["yes"]);
+        assert_eq!(
+            scan_inline_tags_with(text.as_bytes(), &TagPattern::default(), true).unwrap(),
+            vec!["include", "nope", "yes"]
+        );
+    }
+}
@@ -0,0 +1,2214 @@
+//! Collects the tags used in an Obsidian vault, from note front matter and
+//! optionally from inline `#tags` in note bodies.
+
+use anyhow::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{error, warn};
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use thiserror::Error;
+use walkdir::WalkDir;
+use yaml_rust::Yaml;
+
+use anyhow::Result;
+
+pub mod cache;
+pub mod config;
+pub mod inline_tags;
+pub mod lint;
+pub mod output;
+pub mod tree;
+pub mod vault;
+pub mod watch;
+
+use inline_tags::TagPattern;
+use output::TagFiles;
+pub use vault::Vault;
+
+/// Why the front matter of a note could not be read.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum YamlError {
+    #[error("Expected 'tags' to be an array or a string, but found a different type")]
+    InvalidTagsType,
+    #[error("Expected 'aliases' to be an array or a string, but found a different type")]
+    InvalidAliasesType,
+    #[error("Failed to parse YAML front matter: {0}")]
+    ParseError(#[from] yaml_rust::ScanError),
+    #[error("Failed to parse TOML front matter: {0}")]
+    TomlError(#[from] toml::de::Error),
+    #[error("Failed to load file: {0}")]
+    LoadError(#[from] std::io::Error),
+}
+
+impl YamlError {
+    /// The name of the variant, for summaries.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            YamlError::InvalidTagsType => "InvalidTagsType",
+            YamlError::InvalidAliasesType => "InvalidAliasesType",
+            YamlError::ParseError(_) => "ParseError",
+            YamlError::TomlError(_) => "TomlError",
+            YamlError::LoadError(_) => "LoadError",
+        }
+    }
+}
+
+/// Why a vault could not be walked.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum VaultError {
+    #[error("{0}: no such vault")]
+    NotFound(PathBuf),
+    #[error("{0}: vault is not a directory")]
+    NotADirectory(PathBuf),
+    #[error("{0}: {1}")]
+    Unreadable(PathBuf, #[source] std::io::Error),
+}
+
+/// The front matter block of the markdown file at `path`: from a `---` line
+/// to the next `---` (or `...`) line, delimiters included, the closing one
+/// written as `---`. A file starting with a `+++` line has TOML front matter
+/// instead, up to the next `+++` line.
+///
+/// Front matter must be the first non-blank line; otherwise, like when the
+/// file has no `---` line at all, an empty string is returned. Everything
+/// after the opening line is returned when it is never closed. Invalid UTF-8 is replaced
+/// rather than rejected. Fails with [`YamlError::LoadError`] when the file
+/// cannot be read.
+pub fn read_first_section(path: &Path) -> Result<String, YamlError> {
+    read_first_section_with(path, false)
+}
+
+/// [`read_first_section`], also accepting with `legacy_props` the properties
+/// some exporters write without delimiters: `key: value` lines starting the
+/// file, up to the first blank line or line of Markdown. They are returned
+/// wrapped in `---` lines, like regular front matter.
+pub fn read_first_section_with(path: &Path, legacy_props: bool) -> Result<String, YamlError> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    Ok(split_front_matter(reader, legacy_props)?.0)
+}
+
+/// The front matter of `reader` as [`read_first_section_with`] returns it, and
+/// the byte range it spans (empty when there is none).
+fn split_front_matter(
+    reader: impl BufRead,
+    legacy_props: bool,
+) -> std::io::Result<(String, Range<usize>)> {
+    let mut in_section = false;
+    let mut delimiter = FrontmatterDelimiter::Yaml.marker();
+    let mut undelimited = false;
+    let mut current_section = String::new();
+    let mut start = 0;
+    let mut offset = 0;
+
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line_start = offset;
+        offset += line.len() + 1;
+        let line = lossy_line(line);
+        // editors on Windows may save a byte order mark
+        let line = match line.strip_prefix('\u{feff}') {
+            Some(rest) if i == 0 => rest.to_string(),
+            _ => line,
+        };
+
+        let trimmed = line.trim();
+        if undelimited {
+            // mappings may continue on indented lines or with `- item` lists
+            if trimmed.is_empty()
+                || !(is_property_line(&line) || line.starts_with([' ', '\t', '-']))
+            {
+                current_section.push_str("---\n");
+                return Ok((current_section, start..line_start));
+            }
+            current_section.push_str(&line);
+            current_section.push('\n');
+        } else if in_section {
+            // a YAML document may also end with `...`
+            if trimmed == delimiter || (delimiter == "---" && trimmed == "...") {
+                current_section.push_str(delimiter);
+                current_section.push('\n');
+                return Ok((current_section, start..offset));
+            }
+            // Buffer lines in the current section
+            current_section.push_str(&line);
+            current_section.push('\n');
+        } else if trimmed.is_empty() {
+            // blank lines before the front matter are allowed
+        } else {
+            start = line_start;
+            if trimmed == FrontmatterDelimiter::Yaml.marker() {
+                in_section = true;
+            } else if trimmed == FrontmatterDelimiter::Toml.marker() {
+                in_section = true;
+                delimiter = FrontmatterDelimiter::Toml.marker();
+            } else if legacy_props && is_property_line(&line) {
+                undelimited = true;
+                current_section.push_str("---\n");
+                current_section.push_str(&line);
+                current_section.push('\n');
+                continue;
+            } else {
+                // front matter must open the note, a later `---` is a rule
+                break;
+            }
+            current_section.push_str(delimiter);
+            current_section.push('\n');
+        }
+    }
+
+    if undelimited {
+        current_section.push_str("---\n");
+        return Ok((current_section, start..offset));
+    }
+
+    // If we reach the end of the file but no closing `---` is found, return the buffered content.
+    if in_section {
+        return Ok((current_section, start..offset));
+    }
+
+    // If no section is found, return an empty string.
+    Ok((String::new(), 0..0))
+}
+
+/// Whether `line` looks like a top-level `key: value` YAML entry rather than
+/// Markdown: `Note: ...` qualifies, headings, quotes, lists and URLs don't.
+fn is_property_line(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, value)) => {
+            !key.is_empty()
+                && !key.starts_with(|c: char| c.is_whitespace() || "#>-*`|[!".contains(c))
+                && (value.is_empty() || value.starts_with([' ', '\t']))
+        }
+        None => false,
+    }
+}
+
+/// Tags as written, possibly starting with `#`.
+pub type Tags = Vec<String>;
+
+/// The `tags` (and `tag`) front matter entries of the note at `path`, in order
+/// and without duplicates.
+///
+/// Fails with [`YamlError::LoadError`] when the file cannot be read,
+/// [`YamlError::ParseError`] when the front matter is not valid YAML and
+/// [`YamlError::InvalidTagsType`] when `tags` is neither a list nor a string.
+pub fn load_tags(path: &Path) -> Result<Tags, YamlError> {
+    load_tags_with(path, false)
+}
+
+/// [`load_tags`], reading undelimited properties with `legacy_props`, see
+/// [`read_first_section_with`].
+pub fn load_tags_with(path: &Path, legacy_props: bool) -> Result<Tags, YamlError> {
+    load_tags_keyed(path, legacy_props, &[])
+}
+
+/// [`load_tags_with`], also reading the tags at each of `keys`, see
+/// [`parse_tags_keyed`].
+pub fn load_tags_keyed(
+    path: &Path,
+    legacy_props: bool,
+    keys: &[String],
+) -> Result<Tags, YamlError> {
+    let content = read_first_section_with(path, legacy_props)?;
+    parse_tags_keyed(&content, keys)
+}
+
+/// Same as [`load_tags`], for front matter already read into memory.
+pub fn parse_tags(front_matter: &str) -> Result<Tags, YamlError> {
+    parse_tags_keyed(front_matter, &[])
+}
+
+/// [`parse_tags`], followed by the tags at each of `keys`, dotted paths such
+/// as `meta.tags` looked up with [`yaml_path`]. A missing or malformed entry
+/// gives no tags rather than an error, since most notes don't have them.
+pub fn parse_tags_keyed(front_matter: &str, keys: &[String]) -> Result<Tags, YamlError> {
+    let yaml = match parse_yaml(front_matter)? {
+        None => return Ok(Vec::new()),
+        Some(yaml) => yaml,
+    };
+    let mut tags = tags_of(&yaml)?;
+    for key in keys {
+        match yaml_path(&yaml, key).map(tags_from_yaml) {
+            Some(Ok(more)) => tags.extend(more),
+            Some(Err(e)) => warn!("ignoring the '{}' key: {}", key, e),
+            None => {}
+        }
+    }
+    Ok(dedup(tags))
+}
+
+/// The node at the dotted `path` (`meta.tags` is the `tags` key of the `meta`
+/// mapping), `None` when a key is missing or its parent isn't a mapping.
+pub fn yaml_path<'a>(yaml: &'a Yaml, path: &str) -> Option<&'a Yaml> {
+    path.split('.').try_fold(yaml, |node, key| match node {
+        Yaml::Hash(hash) => hash.get(&Yaml::String(key.to_string())),
+        _ => None,
+    })
+}
+
+/// The front matter fields this crate knows about.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FrontmatterData {
+    pub tags: Tags,
+    pub aliases: Vec<String>,
+}
+
+/// The tags and aliases of the note at `path`. Fails like [`load_tags`], and
+/// with [`YamlError::InvalidAliasesType`] when `aliases` is malformed.
+pub fn load_frontmatter(path: &Path) -> Result<FrontmatterData, YamlError> {
+    let content = read_first_section(path)?;
+    parse_frontmatter(&content)
+}
+
+/// Same as [`load_frontmatter`], for front matter already read into memory.
+pub fn parse_frontmatter(front_matter: &str) -> Result<FrontmatterData, YamlError> {
+    match parse_yaml(front_matter)? {
+        None => Ok(FrontmatterData::default()),
+        Some(yaml) => Ok(FrontmatterData {
+            tags: tags_of(&yaml)?,
+            aliases: aliases_of(&yaml)?,
+        }),
+    }
+}
+
+/// Alternative note names from the `aliases` (or `alias`) front matter field.
+pub fn load_aliases(path: &Path) -> Result<Vec<String>, YamlError> {
+    let content = read_first_section(path)?;
+    match parse_yaml(&content)? {
+        None => Ok(Vec::new()),
+        Some(yaml) => aliases_of(&yaml),
+    }
+}
+
+/// The syntax of a front matter block, told by its opening line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontmatterDelimiter {
+    /// YAML between `---` lines, as Obsidian writes it
+    Yaml,
+    /// TOML between `+++` lines, as Hugo writes it
+    Toml,
+}
+
+impl FrontmatterDelimiter {
+    pub fn marker(self) -> &'static str {
+        match self {
+            FrontmatterDelimiter::Yaml => "---",
+            FrontmatterDelimiter::Toml => "+++",
+        }
+    }
+
+    /// The syntax of `front_matter`, as returned by [`read_first_section`].
+    pub fn of(front_matter: &str) -> Self {
+        if front_matter.starts_with(FrontmatterDelimiter::Toml.marker()) {
+            FrontmatterDelimiter::Toml
+        } else {
+            FrontmatterDelimiter::Yaml
+        }
+    }
+}
+
+/// The front matter as YAML, whatever its syntax, so that both are read the
+/// same way.
+fn parse_yaml(front_matter: &str) -> Result<Option<Yaml>, YamlError> {
+    match FrontmatterDelimiter::of(front_matter) {
+        FrontmatterDelimiter::Yaml => {
+            frontmatter::parse(front_matter).map_err(YamlError::ParseError)
+        }
+        FrontmatterDelimiter::Toml => parse_toml(front_matter).map(Some),
+    }
+}
+
+fn parse_toml(front_matter: &str) -> Result<Yaml, YamlError> {
+    let marker = FrontmatterDelimiter::Toml.marker();
+    let body = front_matter.strip_prefix(marker).unwrap_or(front_matter);
+    let body = body.trim_end().strip_suffix(marker).unwrap_or(body);
+    let table: toml::Table = toml::from_str(body)?;
+    let mut yaml = toml_to_yaml(toml::Value::Table(table));
+    // Hugo keeps tags among its taxonomies
+    if yaml["tags"].is_badvalue() {
+        let tags = yaml["taxonomies"]["tags"].clone();
+        if let Yaml::Hash(hash) = &mut yaml {
+            if !tags.is_badvalue() {
+                hash.insert(Yaml::String("tags".to_string()), tags);
+            }
+        }
+    }
+    Ok(yaml)
+}
+
+fn toml_to_yaml(value: toml::Value) -> Yaml {
+    match value {
+        toml::Value::String(s) => Yaml::String(s),
+        toml::Value::Integer(i) => Yaml::Integer(i),
+        toml::Value::Float(f) => Yaml::Real(f.to_string()),
+        toml::Value::Boolean(b) => Yaml::Boolean(b),
+        toml::Value::Datetime(d) => Yaml::String(d.to_string()),
+        toml::Value::Array(values) => Yaml::Array(values.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(
+            table
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// `tags` and `tag` merged. When both keys are present and only one is
+/// malformed, the other one is used and the bad one is logged.
+fn tags_of(yaml: &Yaml) -> Result<Tags, YamlError> {
+    // some plugins write the singular key, treat both the same
+    let (plural, singular) = (&yaml["tags"], &yaml["tag"]);
+    match (tags_from_yaml(plural), tags_from_yaml(singular)) {
+        (Ok(mut tags), Ok(more)) => {
+            tags.extend(more);
+            Ok(dedup(tags))
+        }
+        (Ok(tags), Err(e)) if !plural.is_badvalue() => {
+            warn!("ignoring the 'tag' key: {}", e);
+            Ok(dedup(tags))
+        }
+        (Err(e), Ok(tags)) if !singular.is_badvalue() => {
+            warn!("ignoring the 'tags' key: {}", e);
+            Ok(dedup(tags))
+        }
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    }
+}
+
+fn aliases_of(yaml: &Yaml) -> Result<Vec<String>, YamlError> {
+    let mut aliases = aliases_from_yaml(&yaml["aliases"])?;
+    aliases.extend(aliases_from_yaml(&yaml["alias"])?);
+    Ok(dedup(aliases))
+}
+
+/// Drops repeated entries, keeping the first occurrence.
+fn dedup(mut values: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    values.retain(|value| seen.insert(value.clone()));
+    values
+}
+
+/// `tag` as it is collected: trimmed and without its leading `#`s, so that
+/// `#work` and `work` are the same tag. `None` when nothing is left.
+pub fn normalize_tag(tag: &str) -> Option<&str> {
+    Some(remove_hash(tag.trim()).trim()).filter(|tag| !tag.is_empty())
+}
+
+/// A front matter tag, without the `#` it may be written with like inline.
+fn make_tag(s: &str) -> Option<String> {
+    normalize_tag(s).map(String::from)
+}
+
+fn make_alias(s: &str) -> Option<String> {
+    let s = s.trim();
+    if !s.is_empty() {
+        Some(String::from(s))
+    } else {
+        None
+    }
+}
+
+/// Obsidian accepts a list, or a comma (or space) separated string holding one
+/// or more tags, for `tags`.
+fn tags_from_yaml(value: &Yaml) -> Result<Tags, YamlError> {
+    match value {
+        Yaml::Array(tags) => Ok(tags
+            .iter()
+            .filter_map(|tag| tag.as_str().and_then(make_tag))
+            .collect()),
+        Yaml::String(s) => Ok(s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(make_tag)
+            .collect()),
+        // `BadValue` is what indexing yields when the key is missing
+        Yaml::Null | Yaml::BadValue => Ok(Vec::new()),
+        _ => Err(YamlError::InvalidTagsType),
+    }
+}
+
+/// Same shapes as `tags`, but a string is only split on commas since aliases
+/// may contain spaces.
+fn aliases_from_yaml(value: &Yaml) -> Result<Vec<String>, YamlError> {
+    match value {
+        Yaml::Array(aliases) => Ok(aliases
+            .iter()
+            .filter_map(|alias| alias.as_str().and_then(make_alias))
+            .collect()),
+        Yaml::String(s) => Ok(s.split(',').filter_map(make_alias).collect()),
+        Yaml::Null | Yaml::BadValue => Ok(Vec::new()),
+        _ => Err(YamlError::InvalidAliasesType),
+    }
+}
+
+/// Obsidianタグを収集するイテレータを返す関数
+///
+/// # Arguments
+/// * `directory` - タグを検索するディレクトリパス(UTF-8 でなくてもよい)
+///
+/// # Returns
+/// タグの文字列イテレータ
+///
+/// # Errors
+/// `rg` が起動できない場合はエラー。各行の読み込みエラーはイテレータの要素として返す
+pub fn collect_obsidian_tags(
+    directory: &Path,
+) -> anyhow::Result<impl Iterator<Item = Result<String, std::io::Error>>> {
+    rg_tags(rg_command([directory], &TagPattern::default(), false))
+}
+
+fn rg_tags(
+    command: Command,
+) -> anyhow::Result<impl Iterator<Item = Result<String, std::io::Error>>> {
+    let command = spawn_rg(command)?;
+
+    let stdout = command.stdout.context("cant read from rg process")?;
+    let reader = BufReader::new(stdout);
+    Ok(lossy_lines(reader).map(|line| line.map(|s| remove_hash(s.trim()).to_string())))
+}
+
+/// [`collect_obsidian_tags`] で、各タグを見つけたファイルのパスも返す
+pub fn collect_obsidian_tags_with_files(
+    directory: &Path,
+) -> anyhow::Result<impl Iterator<Item = Result<(PathBuf, String), std::io::Error>>> {
+    rg_tags_with_files(rg_command([directory], &TagPattern::default(), true))
+}
+
+fn rg_tags_with_files(
+    command: Command,
+) -> anyhow::Result<impl Iterator<Item = Result<(PathBuf, String), std::io::Error>>> {
+    let stdout = spawn_rg(command)?
+        .stdout
+        .context("cant read from rg process")?;
+    let reader = BufReader::new(stdout);
+    Ok(reader
+        .split(b'\n')
+        .map(|line| parse_rg_line_with_file(&line?)))
+}
+
+fn spawn_rg(mut command: Command) -> anyhow::Result<Child> {
+    command
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("rgコマンドの実行に失敗")
+}
+
+fn parse_rg_line_with_file(line: &[u8]) -> std::io::Result<(PathBuf, String)> {
+    // the path is kept as raw bytes, it need not be UTF-8
+    let nul = line.iter().position(|&b| b == 0).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unexpected rg output: {:?}", String::from_utf8_lossy(line)),
+        )
+    })?;
+    let tag = remove_hash(String::from_utf8_lossy(&line[nul + 1..]).trim()).to_string();
+    Ok((path_from_bytes(&line[..nul]), tag))
+}
+
+/// Runs `command` to its end, passing each tag found to `found`. Returns
+/// whether rg ran fine; otherwise the failure has been logged, and the tags
+/// found before it were still passed on.
+fn run_rg_with_files(command: Command, mut found: impl FnMut(PathBuf, String)) -> bool {
+    let mut child = match spawn_rg(command) {
+        Ok(child) => child,
+        Err(e) => {
+            error!("{:#}", e);
+            return false;
+        }
+    };
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).split(b'\n') {
+            match line.and_then(|line| parse_rg_line_with_file(&line)) {
+                Ok((path, tag)) => found(path, tag),
+                Err(e) => error!("error occured: {:?}", e),
+            }
+        }
+    }
+    match child.wait() {
+        // 1 only means nothing matched
+        Ok(status) if status.success() || status.code() == Some(1) => true,
+        Ok(status) => {
+            error!("rg failed ({}), its results may be incomplete", status);
+            false
+        }
+        Err(e) => {
+            error!("rg failed: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The lines of `reader` without `\n` or `\r\n`. Invalid UTF-8 is replaced
+/// with U+FFFD instead of failing the whole read.
+pub(crate) fn lossy_lines(reader: impl BufRead) -> impl Iterator<Item = std::io::Result<String>> {
+    reader.split(b'\n').map(|line| Ok(lossy_line(line?)))
+}
+
+fn lossy_line(mut line: Vec<u8>) -> String {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8_lossy(&line).into_owned()
+}
+
+/// `rg` printing each match of `pattern` in `targets`, after the path of its
+/// file when `with_filename` is set.
+fn rg_command<S: AsRef<OsStr>>(
+    targets: impl IntoIterator<Item = S>,
+    pattern: &TagPattern,
+    with_filename: bool,
+) -> Command {
+    let mut command = Command::new("rg");
+    command.arg("--pcre2").arg("-o");
+    // every flag goes before `--`, rg takes whatever follows for a path
+    if with_filename {
+        // `--null` ends the path with NUL instead of `:`, which may appear in file names
+        command.arg("--with-filename").arg("--null");
+    } else {
+        command.arg("--no-filename");
+    }
+    command
+        .arg("--regexp")
+        .arg(pattern.rg_pattern())
+        .arg("--")
+        .args(targets);
+    command
+}
+
+/// Files handed to a single `rg` run, to stay well below the argument size
+/// limit.
+const RG_FILES_PER_RUN: usize = 1000;
+
+/// The `rg` runs searching `files`. They are always listed rather than
+/// leaving the walk to rg, which knows none of our filters and has its own.
+fn rg_commands(files: &[PathBuf], pattern: &TagPattern) -> Vec<Command> {
+    files
+        .chunks(RG_FILES_PER_RUN)
+        .map(|files| rg_command(files, pattern, true))
+        .collect()
+}
+
+/// Whether an `rg` executable can be found on `PATH`.
+pub fn rg_available() -> bool {
+    let exe = if cfg!(windows) { "rg.exe" } else { "rg" };
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(exe).is_file()))
+}
+
+/// Every inline tag occurrence in `files`, through `rg` when it is
+/// installed and the built-in scanner otherwise.
+///
+/// Tags are shaped like `options.tag_pattern`, and
+/// Excalidraw drawings are skipped unless `options.scan_drawings` is set.
+/// When `rg` fails the error is logged and flagged in `options.rg_failed`,
+/// keeping the tags found so far.
+pub fn collect_inline_tags(files: &[PathBuf], options: ScanOptions) -> Result<Vec<String>> {
+    // drawings can only be told apart by looking at the files the tags are in
+    Ok(collect_inline_tag_files(files, options)?
+        .into_iter()
+        .map(|(_, tag)| tag)
+        .collect())
+}
+
+/// Every inline tag occurrence in `files` along with the file it is in, see
+/// [`collect_inline_tags`].
+pub fn collect_inline_tag_files(
+    files: &[PathBuf],
+    options: ScanOptions,
+) -> Result<Vec<(PathBuf, String)>> {
+    let options = ScanOptions {
+        inline: true,
+        ..options
+    };
+    if !rg_available() {
+        warn!("rg not found on PATH, using the built-in inline tag scanner");
+        return Ok(files
+            .par_iter()
+            .flat_map_iter(|path| {
+                load_inline_tags(path, options)
+                    .into_iter()
+                    .map(move |tag| (path.clone(), tag))
+            })
+            .collect());
+    }
+
+    let mut result = Vec::new();
+    let mut drawings = HashMap::new();
+    for command in rg_commands(files, options.tag_pattern()) {
+        let ran = run_rg_with_files(command, |path, tag| {
+            let drawing = *drawings
+                .entry(path.clone())
+                .or_insert_with(|| !options.scan_drawings && is_drawing(&path));
+            if !drawing {
+                result.push((path, tag));
+            }
+        });
+        if !ran {
+            if let Some(failed) = options.rg_failed {
+                failed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// `path` relative to the vault `root`. When the root is reached through a
+/// symlink and `path` is not, both are canonicalized before comparing; paths
+/// outside the vault are returned unchanged.
+pub fn relative_path(root: &Path, path: &Path) -> PathBuf {
+    if let Ok(relative) = path.strip_prefix(root) {
+        return relative.to_path_buf();
+    }
+    match (root.canonicalize(), path.canonicalize()) {
+        (Ok(root), Ok(canonical)) => match canonical.strip_prefix(&root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Files whose front matter could not be loaded, gathered while the rayon
+/// workers scan the vault.
+#[derive(Debug, Default)]
+pub struct ScanErrors(Mutex<Vec<(PathBuf, YamlError)>>);
+
+impl ScanErrors {
+    fn push(&self, path: &Path, error: YamlError) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((path.to_path_buf(), error));
+    }
+
+    pub fn into_inner(self) -> Vec<(PathBuf, YamlError)> {
+        self.0.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// What to read from each file besides its front matter `tags`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanOptions<'a> {
+    /// Also extract inline `#tags` with the built-in scanner
+    pub inline: bool,
+    /// Where to record files whose front matter could not be loaded
+    pub errors: Option<&'a ScanErrors>,
+    /// Only keep tags under one of these, see [`has_prefix`]. Empty keeps all
+    pub prefixes: &'a [String],
+    /// Where to look up front matter tags before parsing a note. Only used
+    /// when `inline` is off, since the note is read anyway otherwise
+    pub cache: Option<&'a cache::TagCache>,
+    /// Also read properties written without `---` delimiters, see
+    /// [`read_first_section_with`]
+    pub legacy_props: bool,
+    /// More front matter keys holding tags, see [`parse_tags_keyed`]
+    pub tags_keys: &'a [String],
+    /// Shape of inline tags, the default [`TagPattern`] when `None`
+    pub tag_pattern: Option<&'a TagPattern>,
+    /// Also extract inline tags from Excalidraw drawings, see [`is_drawing`]
+    pub scan_drawings: bool,
+    /// Also extract inline tags from fenced code blocks and code spans
+    pub include_code: bool,
+    /// Advanced by one for each file scanned
+    pub progress: Option<&'a indicatif::ProgressBar>,
+    /// Set when `rg` could not be run or failed. What it found until then is
+    /// still used
+    pub rg_failed: Option<&'a AtomicBool>,
+    /// Only keep tags of at least this many characters, `#` not included
+    pub min_length: usize,
+    /// Only keep tags of at most this many characters, `#` not included
+    pub max_length: Option<usize>,
+}
+
+impl ScanOptions<'_> {
+    /// The pattern inline tags are matched with.
+    pub fn tag_pattern(&self) -> &TagPattern {
+        self.tag_pattern.unwrap_or(&inline_tags::DEFAULT_PATTERN)
+    }
+
+    /// Whether `tag` passes the `prefixes` and length filters.
+    pub fn keeps(&self, tag: &str) -> bool {
+        let tag = remove_hash(tag);
+        let length = tag.chars().count();
+        length >= self.min_length
+            && self.max_length.is_none_or(|max| length <= max)
+            && (self.prefixes.is_empty()
+                || self.prefixes.iter().any(|prefix| has_prefix(tag, prefix)))
+    }
+}
+
+/// Whether `tag` is `prefix` or one of its nested tags: `project` matches
+/// `project` and `project/alpha` but not `projects`.
+pub fn has_prefix(tag: &str, prefix: &str) -> bool {
+    let prefix = remove_hash(prefix).trim_end_matches('/');
+    tag.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Whether `path` is an Excalidraw drawing: a note whose name ends with
+/// `.excalidraw.md` or whose front matter has `excalidraw-plugin: parsed`.
+/// Its body is a JSON scene, full of `#ffffff` colors that look like tags.
+pub fn is_drawing(path: &Path) -> bool {
+    has_drawing_name(path)
+        || read_first_section(path).is_ok_and(|section| has_drawing_front_matter(&section))
+}
+
+fn has_drawing_name(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name.to_string_lossy()
+            .to_lowercase()
+            .ends_with(".excalidraw.md")
+    })
+}
+
+fn has_drawing_front_matter(section: &str) -> bool {
+    section.lines().any(|line| {
+        line.split_once(':').is_some_and(|(key, value)| {
+            key.trim() == "excalidraw-plugin" && value.trim().trim_matches(['"', '\'']) == "parsed"
+        })
+    })
+}
+
+/// `tag` without the longest of `prefixes` it is nested under (see
+/// [`has_prefix`]) and the `/` after it: `status/done` becomes `done`. Tags
+/// under none of them, or equal to one, are returned as they are.
+pub fn strip_tag_prefix<'a>(tag: &'a str, prefixes: &[String]) -> &'a str {
+    let tag = remove_hash(tag);
+    prefixes
+        .iter()
+        .map(|prefix| remove_hash(prefix).trim_end_matches('/'))
+        .filter(|prefix| has_prefix(tag, prefix))
+        .max_by_key(|prefix| prefix.len())
+        .and_then(|prefix| tag[prefix.len()..].strip_prefix('/'))
+        .unwrap_or(tag)
+}
+
+/// Front matter tags of `path`. Failures are logged as errors and recorded in
+/// `options.errors`, and the file then contributes no front matter tags.
+fn load_tags_reporting(path: &Path, options: ScanOptions) -> Option<Tags> {
+    let tags = match options.cache {
+        Some(cache) => cache.load_tags(path, options.legacy_props, options.tags_keys),
+        None => load_tags_keyed(path, options.legacy_props, options.tags_keys),
+    };
+    keep_reporting(path, tags, options)
+}
+
+fn keep_reporting(
+    path: &Path,
+    tags: Result<Tags, YamlError>,
+    options: ScanOptions,
+) -> Option<Tags> {
+    match tags {
+        Ok(mut tags) => {
+            tags.retain(|tag| options.keeps(tag));
+            Some(tags)
+        }
+        Err(e) => {
+            error!("{}: {}", path.display(), e);
+            if let Some(errors) = options.errors {
+                errors.push(path, e);
+            }
+            None
+        }
+    }
+}
+
+fn load_inline_tags(path: &Path, options: ScanOptions) -> Tags {
+    if !options.inline || (!options.scan_drawings && is_drawing(path)) {
+        return Vec::new();
+    }
+    let mut tags =
+        inline_tags::extract_inline_tags_with(path, options.tag_pattern(), options.include_code)
+            .unwrap_or_else(|e| {
+                warn!("{}: {}", path.display(), e);
+                Vec::new()
+            });
+    tags.retain(|tag| options.keeps(tag));
+    tags
+}
+
+/// Front matter tags of `path`, followed by its inline tags when enabled.
+/// A file whose front matter is broken still yields its inline tags.
+fn scan_file(path: &Path, options: ScanOptions) -> Tags {
+    let (mut tags, inline) = scan_note(path, options);
+    tags.extend(inline);
+    tags
+}
+
+/// The front matter tags and, when enabled, the inline tags of the body of
+/// `path`, reading the file only once.
+fn scan_note(path: &Path, options: ScanOptions) -> (Tags, Tags) {
+    if let Some(progress) = options.progress {
+        progress.inc(1);
+    }
+    if !options.inline {
+        return (
+            load_tags_reporting(path, options).unwrap_or_default(),
+            Vec::new(),
+        );
+    }
+
+    let (front, mut inline) = match options.cache {
+        Some(cache) => {
+            let scan = cache::InlineScan {
+                pattern: options.tag_pattern().rg_pattern(),
+                include_code: options.include_code,
+                scan_drawings: options.scan_drawings,
+            };
+            cache.scan_note(path, options.legacy_props, options.tags_keys, &scan, || {
+                read_note(path, options)
+            })
+        }
+        None => read_note(path, options),
+    };
+    let front = keep_reporting(path, front, options).unwrap_or_default();
+    inline.retain(|tag| options.keeps(tag));
+    (front, inline)
+}
+
+/// The front matter and inline tags of `path`, unfiltered.
+fn read_note(path: &Path, options: ScanOptions) -> (Result<Tags, YamlError>, Tags) {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return (Err(e.into()), Vec::new()),
+    };
+    let (section, range) =
+        split_front_matter(&bytes[..], options.legacy_props).expect("reading from memory");
+    let front = parse_tags_keyed(&section, options.tags_keys);
+    if !options.scan_drawings && (has_drawing_name(path) || has_drawing_front_matter(&section)) {
+        return (front, Vec::new());
+    }
+
+    let end = range.end.min(bytes.len());
+    let body = (&bytes[..range.start]).chain(&bytes[end..]);
+    let inline =
+        inline_tags::scan_inline_tags_with(body, options.tag_pattern(), options.include_code)
+            .expect("reading from memory");
+    (front, inline)
+}
+
+/// Every tag (without `#`, see [`normalize_tag`]) in `paths`, scanned in parallel.
+///
+/// Files whose front matter cannot be loaded are logged, recorded in
+/// `options.errors` and contribute only their inline tags; this never fails.
+pub fn collect_tags(paths: &[PathBuf], options: ScanOptions) -> Result<HashSet<String>> {
+    let result = paths
+        .into_par_iter()
+        .flat_map_iter(|path| scan_file(path, options))
+        .collect();
+    Ok(result)
+}
+
+/// Calls `emit` with each tag (without `#`) of `paths` the first time it is
+/// found, while the notes are still being scanned in parallel, and returns
+/// every tag found. Unreadable files are handled as in [`collect_tags`]; an
+/// error from `emit` stops the scan.
+pub fn stream_tags<E: Send>(
+    paths: &[PathBuf],
+    options: ScanOptions,
+    emit: impl Fn(&str) -> Result<(), E> + Sync,
+) -> Result<HashSet<String>, E> {
+    let seen = Mutex::new(HashSet::new());
+    paths.par_iter().try_for_each(|path| {
+        for tag in scan_file(path, options) {
+            let tag = remove_hash(&tag);
+            let new = seen
+                .lock()
+                .expect("tag set poisoned")
+                .insert(tag.to_string());
+            if new {
+                emit(tag)?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(seen.into_inner().expect("tag set poisoned"))
+}
+
+/// Tag (without `#`) to the number of notes having it, in their front matter
+/// or inline, however often they repeat it.
+pub type TagCounts = HashMap<String, usize>;
+
+/// Notes of `paths` having each tag, see [`TagCounts`]. Unreadable files are
+/// handled as in [`collect_tags`].
+pub fn count_tags(paths: &[PathBuf], options: ScanOptions) -> TagCounts {
+    paths
+        .par_iter()
+        .fold(TagCounts::new, |mut acc, path| {
+            let (front, inline) = scan_note(path, options);
+            add_counts(&mut acc, front.into_iter().chain(inline));
+            acc
+        })
+        .reduce(TagCounts::new, |mut acc, other| {
+            for (tag, count) in other {
+                *acc.entry(tag).or_default() += count;
+            }
+            acc
+        })
+}
+
+/// Counts one more note for each distinct tag of `tags`, the tags of a
+/// single note, after dropping their `#`.
+pub fn add_counts(counts: &mut TagCounts, tags: impl IntoIterator<Item = String>) {
+    let unique: HashSet<String> = tags
+        .into_iter()
+        .map(|tag| remove_hash(&tag).to_string())
+        .collect();
+    for tag in unique {
+        *counts.entry(tag).or_default() += 1;
+    }
+}
+
+/// Most used tags first, ties broken by name.
+pub fn sorted_counts(counts: &TagCounts) -> Vec<(&str, usize)> {
+    let mut sorted: Vec<_> = counts
+        .iter()
+        .map(|(tag, &count)| (tag.as_str(), count))
+        .collect();
+    sorted.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    sorted
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortMode {
+    /// Lexicographic
+    Alpha,
+    /// Lexicographic ignoring case, so `Zettel` comes after `apple`
+    AlphaCi,
+    /// Most used first
+    #[value(alias = "count")]
+    Freq,
+    /// Shortest first
+    Length,
+    /// As collected, which changes between runs but saves sorting huge vaults
+    #[value(name = "none")]
+    Unsorted,
+}
+
+/// Compares tags by their lowercase form, then as written so that the order
+/// doesn't depend on the input.
+pub fn cmp_ignore_case(a: &str, b: &str) -> std::cmp::Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+/// Orders `(tag, count)` pairs by `mode`, ties broken by name.
+pub fn sort_tags(mut tags: Vec<(String, usize)>, mode: SortMode) -> Vec<(String, usize)> {
+    match mode {
+        SortMode::Alpha => tags.sort_unstable_by(|a, b| a.0.cmp(&b.0)),
+        SortMode::AlphaCi => tags.sort_unstable_by(|a, b| cmp_ignore_case(&a.0, &b.0)),
+        SortMode::Freq => tags.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+        SortMode::Length => tags.sort_unstable_by(|a, b| {
+            a.0.chars()
+                .count()
+                .cmp(&b.0.chars().count())
+                .then_with(|| a.0.cmp(&b.0))
+        }),
+        SortMode::Unsorted => {}
+    }
+    tags
+}
+
+/// The tags of each file in `paths` (without `#`, deduplicated), in the order
+/// of `paths`. Untagged files are kept with an empty list.
+pub fn collect_file_tags(paths: &[PathBuf], options: ScanOptions) -> Vec<(PathBuf, Tags)> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let tags = scan_file(path, options)
+                .iter()
+                .map(|tag| remove_hash(tag).to_string())
+                .collect();
+            (path.clone(), dedup(tags))
+        })
+        .collect()
+}
+
+/// Drops tags that differ from an earlier one only by case, keeping the
+/// casing seen first. Uses Unicode lowercasing, so scripts without case pass
+/// through untouched.
+pub fn fold_case(tags: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut folded = Vec::new();
+    for tag in tags {
+        if let Entry::Vacant(entry) = seen.entry(tag.to_lowercase()) {
+            entry.insert(tag.clone());
+            folded.push(tag);
+        }
+    }
+    folded
+}
+
+/// [`fold_case`] for a [`TagFiles`] map: the files of tags merged together
+/// are joined, under the casing that sorts first.
+pub fn fold_case_tag_files(tag_files: TagFiles) -> TagFiles {
+    let mut keys: HashMap<String, String> = HashMap::new();
+    let mut folded = TagFiles::new();
+    for (tag, files) in tag_files {
+        let key = keys.entry(tag.to_lowercase()).or_insert(tag);
+        folded.entry(key.clone()).or_default().extend(files);
+    }
+    folded
+}
+
+/// Every alias defined in the front matter of `paths`. Files whose aliases
+/// cannot be read are logged and skipped.
+pub fn collect_aliases(paths: &[PathBuf]) -> HashSet<String> {
+    paths
+        .par_iter()
+        .flat_map_iter(|path| {
+            load_aliases(path).unwrap_or_else(|e| {
+                warn!("{}: {}", path.display(), e);
+                Vec::new()
+            })
+        })
+        .collect()
+}
+
+/// The files among `paths` carrying a tag (front matter or inline) that
+/// matches `pattern`, sorted. `*` also matches `/`, so `project/*` finds every
+/// subtag of `project`.
+pub fn files_with_tag(
+    paths: &[PathBuf],
+    pattern: &glob::Pattern,
+    options: ScanOptions,
+) -> Vec<PathBuf> {
+    let options = ScanOptions {
+        inline: true,
+        ..options
+    };
+    let mut result: Vec<PathBuf> = paths
+        .par_iter()
+        .filter(|path| {
+            scan_file(path, options)
+                .iter()
+                .any(|tag| pattern.matches(remove_hash(tag)))
+        })
+        .cloned()
+        .collect();
+    result.sort();
+    result
+}
+
+/// Tag to the files (relative to `root`) carrying it. Unreadable files are
+/// handled as in [`collect_tags`].
+pub fn collect_tag_files(root: &Path, paths: &[PathBuf], options: ScanOptions) -> TagFiles {
+    paths
+        .par_iter()
+        .fold(TagFiles::new, |mut acc, path| {
+            let relative = relative_path(root, path);
+            for tag in scan_file(path, options) {
+                acc.entry(remove_hash(&tag).to_string())
+                    .or_default()
+                    .insert(relative.clone());
+            }
+            acc
+        })
+        .reduce(TagFiles::new, |mut acc, other| {
+            for (tag, files) in other {
+                acc.entry(tag).or_default().extend(files);
+            }
+            acc
+        })
+}
+
+/// Which parts of the vault [`collect_paths_with`] walks into.
+#[derive(Clone, Debug)]
+pub struct WalkOptions {
+    /// Walk into directories starting with `.` (`.obsidian`, `.trash`, `.git`, ...)
+    pub include_hidden: bool,
+    /// Vault-relative paths to skip, along with everything below them
+    pub exclude: GlobSet,
+    /// When set, only files whose vault-relative path matches are kept.
+    /// Applied after `exclude`, so an excluded file stays excluded
+    pub include: Option<GlobSet>,
+    /// Skip what `.gitignore`, `.ignore` and the global git excludes ignore
+    pub respect_gitignore: bool,
+    /// Extensions of the files to collect, compared case-insensitively
+    pub extensions: Vec<String>,
+    /// Collect `.canvas` files even when their extension is listed. They are
+    /// JSON, not notes, so they are skipped by default
+    pub include_canvas: bool,
+    /// How deep to walk: the root itself is at depth 0, so 1 only collects the
+    /// files directly in it, 2 those of its subdirectories too, and so on.
+    /// Unlimited when `None`
+    pub max_depth: Option<usize>,
+    /// Walk into symlinked directories; a symlink back to one of its parents is
+    /// logged and skipped. Symlinked notes are collected either way
+    pub follow_symlinks: bool,
+    /// Only collect files modified at or after this time
+    pub since: Option<SystemTime>,
+    /// Only collect files modified before this time
+    pub until: Option<SystemTime>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            include_hidden: false,
+            exclude: GlobSet::empty(),
+            include: None,
+            respect_gitignore: false,
+            extensions: vec!["md".to_string()],
+            include_canvas: false,
+            max_depth: None,
+            follow_symlinks: false,
+            since: None,
+            until: None,
+        }
+    }
+}
+
+/// Obsidian's own config and trash folders, excluded unless asked otherwise.
+pub const DEFAULT_EXCLUDES: &[&str] = &[".obsidian/**", ".trash/**"];
+
+/// Compiles `--exclude` style glob patterns.
+pub fn build_globset<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid glob {:?}", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// The markdown (`.md`, in any case) files under `root`, skipping hidden
+/// entries.
+/// Unreadable directories are silently skipped, but `root` itself must be an
+/// existing directory.
+pub fn collect_paths(root: &Path) -> Result<Vec<PathBuf>, VaultError> {
+    collect_paths_with(root, &WalkOptions::default())
+}
+
+/// Names of the files of gitignore-style patterns of files to skip, read
+/// whenever the vault root has one (its folders may then have their own),
+/// relative to their folder. They come after `exclude` and before the
+/// gitignore rules; the later name wins when a folder has both.
+pub const IGNORE_FILES: [&str; 2] = [".get-tags-ignore", ".obsidian-get-tags-ignore"];
+
+/// Whether the vault at `root` has one of the [`IGNORE_FILES`] at its root.
+fn has_ignore_file(root: &Path) -> bool {
+    IGNORE_FILES.iter().any(|name| root.join(name).is_file())
+}
+
+/// Same as [`collect_paths`], walking as described by `options`.
+pub fn collect_paths_with(root: &Path, options: &WalkOptions) -> Result<Vec<PathBuf>, VaultError> {
+    match root.try_exists() {
+        Ok(true) if root.is_dir() => {}
+        Ok(true) => return Err(VaultError::NotADirectory(root.to_path_buf())),
+        Ok(false) => return Err(VaultError::NotFound(root.to_path_buf())),
+        Err(e) => return Err(VaultError::Unreadable(root.to_path_buf(), e)),
+    }
+    if options.respect_gitignore || has_ignore_file(root) {
+        return Ok(collect_paths_ignoring(root, options));
+    }
+    let mut walk = WalkDir::new(root).follow_links(options.follow_symlinks);
+    if let Some(depth) = options.max_depth {
+        walk = walk.max_depth(depth);
+    }
+    let paths: Vec<_> = walk
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || is_walked(root, entry.path(), entry.file_name(), options)
+        })
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            // unreadable directories, and symlinks leading back to an ancestor
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        })
+        .filter(|path| {
+            is_note(path, options)
+                && is_included(root, path, options)
+                && is_in_time_range(path, options)
+        })
+        .collect();
+    Ok(paths)
+}
+
+/// Same walk as [`collect_paths_with`] through the `ignore` crate, which reads
+/// the [`IGNORE_FILES`] and, when asked, the gitignore rules, even when the
+/// vault isn't a git repository.
+fn collect_paths_ignoring(root: &Path, options: &WalkOptions) -> Vec<PathBuf> {
+    // `filter_entry` needs an owned, `'static` closure
+    let walk = options.clone();
+    let root_path = root.to_path_buf();
+    let mut builder = ignore::WalkBuilder::new(root);
+    for name in IGNORE_FILES {
+        builder.add_custom_ignore_filename(name);
+    }
+    builder
+        .standard_filters(options.respect_gitignore)
+        // hidden entries are handled by `is_walked` like in the default walk
+        .hidden(false)
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .follow_links(options.follow_symlinks)
+        .filter_entry(move |entry| {
+            entry.depth() == 0 || is_walked(&root_path, entry.path(), entry.file_name(), &walk)
+        })
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        })
+        .filter(|path| {
+            is_note(path, options)
+                && is_included(root, path, options)
+                && is_in_time_range(path, options)
+        })
+        .collect()
+}
+
+/// Whether the walk enters (or lists) the entry at `path` below `root`.
+fn is_walked(root: &Path, path: &Path, file_name: &OsStr, options: &WalkOptions) -> bool {
+    let hidden = file_name.to_string_lossy().starts_with('.');
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    (options.include_hidden || !hidden) && !options.exclude.is_match(relative)
+}
+
+/// Whether a walk of `root` with `options` would collect `path`, which may
+/// no longer exist. Gitignore rules and modification times are not checked.
+pub fn is_collected(root: &Path, path: &Path, options: &WalkOptions) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    if options
+        .max_depth
+        .is_some_and(|depth| relative.components().count() > depth)
+    {
+        return false;
+    }
+    let mut prefix = root.to_path_buf();
+    for component in relative.components() {
+        prefix.push(component);
+        if !is_walked(root, &prefix, component.as_os_str(), options) {
+            return false;
+        }
+    }
+    has_extension(path, options) && is_included(root, path, options)
+}
+
+fn is_included(root: &Path, path: &Path, options: &WalkOptions) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    options
+        .include
+        .as_ref()
+        .is_none_or(|include| include.is_match(relative))
+}
+
+/// Whether `path` was modified within `options.since` and `options.until`.
+/// Files whose modification time can't be read are kept, with a warning.
+fn is_in_time_range(path: &Path, options: &WalkOptions) -> bool {
+    if options.since.is_none() && options.until.is_none() {
+        return true;
+    }
+    match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(mtime) => {
+            options.since.is_none_or(|since| mtime >= since)
+                && options.until.is_none_or(|until| mtime < until)
+        }
+        Err(e) => {
+            warn!(
+                "{}: keeping the file, its modification time is unknown: {}",
+                path.display(),
+                e
+            );
+            true
+        }
+    }
+}
+
+/// A point in time given on the command line: a duration before now (`7d`,
+/// `24h`, `1week 2days`), a local date (`2024-06-01`, meaning its midnight)
+/// or a UTC timestamp (`2024-06-01T12:00:00Z`).
+pub fn parse_time(arg: &str) -> std::result::Result<SystemTime, String> {
+    if let Ok(duration) = humantime::parse_duration(arg) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| format!("{} ago is out of range", arg));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(arg, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+        return midnight
+            .and_local_timezone(chrono::Local)
+            .earliest()
+            .map(SystemTime::from)
+            .ok_or_else(|| format!("{} has no midnight in the local time zone", arg));
+    }
+    humantime::parse_rfc3339_weak(arg).map_err(|_| {
+        format!(
+            "expected a duration like `7d` or `24h`, a date like `2024-06-01` or a \
+             timestamp like `2024-06-01T12:00:00Z`, not `{}`",
+            arg
+        )
+    })
+}
+
+fn is_note(path: &Path, options: &WalkOptions) -> bool {
+    path.is_file() && has_extension(path, options)
+}
+
+/// Whether `path` has one of the extensions of `options`, canvases only when
+/// they are included.
+pub fn has_extension(path: &Path, options: &WalkOptions) -> bool {
+    path.extension().is_some_and(|ext| {
+        (options.include_canvas || !ext.eq_ignore_ascii_case("canvas"))
+            && options
+                .extensions
+                .iter()
+                .any(|wanted| ext.eq_ignore_ascii_case(wanted.trim_start_matches('.')))
+    })
+}
+
+/// Newline-separated paths, as printed by `fd` or `git diff --name-only`.
+/// Blank lines are ignored and `~` is expanded. Paths that don't exist, can't
+/// be opened or aren't files are logged as errors and dropped. Unlike
+/// [`collect_paths`], any extension is accepted.
+pub fn read_paths(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    read_paths_from(reader, b'\n', Path::new(""))
+}
+
+/// [`read_paths`], separated by `separator` (`b'\0'` for `find -print0`)
+/// and with relative paths taken from `base`.
+pub fn read_paths_from(reader: impl BufRead, separator: u8, base: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for line in reader.split(separator) {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let path = match std::str::from_utf8(&line) {
+            Ok(line) => expanduser::expanduser(line)?,
+            Err(_) => path_from_bytes(&line),
+        };
+        let path = base.join(path);
+        match File::open(&path) {
+            Ok(_) if path.is_file() => paths.push(path),
+            Ok(_) => error!("{}: not a file", path.display()),
+            Err(e) => error!("{}: {}", path.display(), e),
+        }
+    }
+    Ok(paths)
+}
+
+/// `s` without its leading `#`s.
+pub fn remove_hash(s: &str) -> &str {
+    s.trim_start_matches('#')
+}
+
+/// The `/` ancestors of `tag` followed by `tag` itself: `a/b/c` gives `a`,
+/// `a/b` and `a/b/c`. Empty segments (`a//b`, `/a`) add no ancestor.
+pub fn parent_tags(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices('/')
+        .map(move |(i, _)| &tag[..i])
+        .filter(|parent| !parent.is_empty() && !parent.ends_with('/'))
+        .chain(std::iter::once(tag))
+}
+
+/// The strict `/` ancestors of any of `tags`. Tags not in it are leaves:
+/// of `project`, `project/alpha` and `project/alpha/task` only the last.
+pub fn ancestor_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> HashSet<&'a str> {
+    tags.into_iter()
+        .flat_map(|tag| parent_tags(tag).filter(move |parent| *parent != tag))
+        .collect()
+}
+
+/// Counts every occurrence of a nested tag toward each of its ancestors too.
+pub fn expand_counts(counts: TagCounts) -> TagCounts {
+    let mut expanded = TagCounts::new();
+    for (tag, count) in &counts {
+        for parent in parent_tags(tag) {
+            *expanded.entry(parent.to_string()).or_default() += count;
+        }
+    }
+    expanded
+}
+
+/// Lists the files of a nested tag under each of its ancestors too.
+pub fn expand_tag_files(tag_files: TagFiles) -> TagFiles {
+    let mut expanded = TagFiles::new();
+    for (tag, files) in &tag_files {
+        for parent in parent_tags(tag) {
+            expanded
+                .entry(parent.to_string())
+                .or_default()
+                .extend(files.iter().cloned());
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod test_common;
+
+#[cfg(test)]
+mod tests {
+    use super::test_common::fixture_vault;
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn tag_files_are_relative_and_deduplicated() {
+        let root = fixture_vault(&[
+            ("a.md", "---\ntags: [foo, foo, bar]\n---\n"),
+            ("sub/b.md", "---\ntags: [\"#foo\"]\n---\n"),
+            ("broken.md", "---\ntags: {foo: 1}\n---\n"),
+        ]);
+
+        let tag_files = collect_tag_files(
+            &root,
+            &collect_paths(&root).unwrap(),
+            ScanOptions::default(),
+        );
+
+        assert_eq!(tag_files.len(), 2);
+        assert_eq!(tag_files["bar"], BTreeSet::from([PathBuf::from("a.md")]));
+        assert_eq!(
+            tag_files["foo"],
+            BTreeSet::from([PathBuf::from("a.md"), PathBuf::from("sub/b.md")])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_path_through_symlinked_root() {
+        let root = fixture_vault(&[("vault/dir/note.md", "")]);
+        let (vault, link) = (root.join("vault"), root.join("link"));
+        std::os::unix::fs::symlink(&vault, &link).unwrap();
+
+        let relative = relative_path(&link, &vault.join("dir/note.md"));
+
+        assert_eq!(relative, PathBuf::from("dir/note.md"));
+    }
+
+    #[test]
+    fn hidden_directories_are_skipped_unless_asked() {
+        let root = fixture_vault(&[
+            ("note.md", ""),
+            (".obsidian/plugins/x/README.md", ""),
+            (".trash/old.md", ""),
+            ("templates/daily.md", ""),
+            (".hidden/note.md", ""),
+        ]);
+        let relative = |options: &WalkOptions| {
+            let mut paths: Vec<_> = collect_paths_with(&root, options)
+                .unwrap()
+                .into_iter()
+                .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        let default = relative(&WalkOptions::default());
+        let hidden = relative(&WalkOptions {
+            include_hidden: true,
+            ..Default::default()
+        });
+        let excluded = relative(&WalkOptions {
+            exclude: build_globset(&["templates"]).unwrap(),
+            ..Default::default()
+        });
+        let default_excludes = relative(&WalkOptions {
+            include_hidden: true,
+            exclude: build_globset(DEFAULT_EXCLUDES).unwrap(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            default,
+            vec![
+                PathBuf::from("note.md"),
+                PathBuf::from("templates/daily.md")
+            ]
+        );
+        assert_eq!(
+            hidden,
+            vec![
+                PathBuf::from(".hidden/note.md"),
+                PathBuf::from(".obsidian/plugins/x/README.md"),
+                PathBuf::from(".trash/old.md"),
+                PathBuf::from("note.md"),
+                PathBuf::from("templates/daily.md"),
+            ]
+        );
+        assert_eq!(excluded, vec![PathBuf::from("note.md")]);
+        assert_eq!(
+            default_excludes,
+            vec![
+                PathBuf::from(".hidden/note.md"),
+                PathBuf::from("note.md"),
+                PathBuf::from("templates/daily.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn exclude_accepts_directory_and_file_globs() {
+        let root = fixture_vault(&[
+            ("note.md", ""),
+            ("Archive/2020/old.md", ""),
+            ("drafts/a.draft.md", ""),
+            ("drafts/b.md", ""),
+        ]);
+        let options = WalkOptions {
+            exclude: build_globset(&["Archive/**", "**/*.draft.md"]).unwrap(),
+            ..Default::default()
+        };
+        let mut paths = collect_paths_with(&root, &options).unwrap();
+        paths.sort();
+
+        assert_eq!(paths, vec![root.join("drafts/b.md"), root.join("note.md")]);
+    }
+
+    #[test]
+    fn max_depth_counts_the_root_as_zero() {
+        let root = fixture_vault(&[("top.md", ""), ("a/mid.md", ""), ("a/b/deep.md", "")]);
+        let walk = |max_depth| {
+            let options = WalkOptions {
+                max_depth,
+                ..Default::default()
+            };
+            let mut paths = collect_paths_with(&root, &options).unwrap();
+            paths.sort();
+            assert!(paths.iter().all(|path| is_collected(&root, path, &options)));
+            paths
+        };
+        let (zero, one, two, all) = (walk(Some(0)), walk(Some(1)), walk(Some(2)), walk(None));
+
+        assert!(zero.is_empty());
+        assert_eq!(one, vec![root.join("top.md")]);
+        assert_eq!(two, vec![root.join("a/mid.md"), root.join("top.md")]);
+        assert_eq!(all.len(), 3);
+        assert!(!is_collected(
+            &root,
+            &root.join("a/b/deep.md"),
+            &WalkOptions {
+                max_depth: Some(2),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_followed_when_asked_without_looping() {
+        let root = fixture_vault(&[("note.md", "")]);
+        let target = fixture_vault(&[("linked.md", "")]);
+        std::os::unix::fs::symlink(&target, root.join("link")).unwrap();
+        // a cycle, reported and skipped
+        std::os::unix::fs::symlink(&target, target.join("loop")).unwrap();
+
+        let walk = |follow_symlinks| {
+            let mut paths = collect_paths_with(
+                &root,
+                &WalkOptions {
+                    follow_symlinks,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            paths.sort();
+            paths
+        };
+        let (kept, followed) = (walk(false), walk(true));
+
+        assert_eq!(kept, vec![root.join("note.md")]);
+        assert_eq!(
+            followed,
+            vec![root.join("link/linked.md"), root.join("note.md")]
+        );
+    }
+
+    #[test]
+    fn since_and_until_filter_by_mtime() {
+        use filetime::{set_file_mtime, FileTime};
+
+        let root = fixture_vault(&[("old.md", ""), ("new.md", "")]);
+        let day = 24 * 60 * 60;
+        let now = FileTime::now().unix_seconds();
+        set_file_mtime(
+            root.join("old.md"),
+            FileTime::from_unix_time(now - 30 * day, 0),
+        )
+        .unwrap();
+
+        let walk = |since: &str, until: Option<&str>| {
+            let options = WalkOptions {
+                since: Some(parse_time(since).unwrap()),
+                until: until.map(|until| parse_time(until).unwrap()),
+                ..Default::default()
+            };
+            collect_paths_with(&root, &options).unwrap()
+        };
+        let recent = walk("7d", None);
+        let older = walk("60d", Some("7d"));
+        let dated = walk("1970-01-02", None);
+
+        assert_eq!(recent, vec![root.join("new.md")]);
+        assert_eq!(older, vec![root.join("old.md")]);
+        assert_eq!(dated.len(), 2);
+        assert!(parse_time("last tuesday").is_err());
+    }
+
+    #[test]
+    fn extensions_are_case_insensitive() {
+        let root = fixture_vault(&[
+            ("a.md", ""),
+            ("b.MD", ""),
+            ("c.markdown", ""),
+            ("d.txt", ""),
+        ]);
+        let mut default = collect_paths(&root).unwrap();
+        default.sort();
+        let options = WalkOptions {
+            extensions: vec![".markdown".to_string(), "TXT".to_string()],
+            ..Default::default()
+        };
+        let mut custom = collect_paths_with(&root, &options).unwrap();
+        custom.sort();
+
+        assert_eq!(default, vec![root.join("a.md"), root.join("b.MD")]);
+        assert_eq!(custom, vec![root.join("c.markdown"), root.join("d.txt")]);
+    }
+
+    #[test]
+    fn include_keeps_only_matching_files() {
+        let root = fixture_vault(&[
+            ("note.md", ""),
+            ("Projects/a.md", ""),
+            ("Projects/old/b.md", ""),
+        ]);
+        let options = WalkOptions {
+            include: Some(build_globset(&["Projects/**"]).unwrap()),
+            exclude: build_globset(&["Projects/old"]).unwrap(),
+            ..Default::default()
+        };
+        let paths = collect_paths_with(&root, &options).unwrap();
+
+        assert_eq!(paths, vec![root.join("Projects/a.md")]);
+    }
+
+    #[test]
+    fn files_with_tag_matches_globs() {
+        let root = fixture_vault(&[
+            ("a.md", "---\ntags: [project/active]\n---\n"),
+            ("b.md", "body #project/done/2024\n"),
+            ("c.md", "---\ntags: [projects]\n---\n"),
+        ]);
+        let paths = collect_paths(&root).unwrap();
+        let names = |pattern: &str| -> Vec<PathBuf> {
+            files_with_tag(
+                &paths,
+                &glob::Pattern::new(pattern).unwrap(),
+                ScanOptions::default(),
+            )
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect()
+        };
+
+        let active = names("project/active");
+        let all = names("project/*");
+
+        assert_eq!(active, vec![PathBuf::from("a.md")]);
+        assert_eq!(all, vec![PathBuf::from("a.md"), PathBuf::from("b.md")]);
+    }
+
+    #[test]
+    fn fold_case_keeps_first_casing() {
+        let tags = ["ProjectX", "日本語", "projectx", "Café", "CAFÉ", "日本語"].map(String::from);
+        assert_eq!(fold_case(tags), vec!["ProjectX", "日本語", "Café"]);
+    }
+
+    #[test]
+    fn reads_front_matter_with_bom_and_crlf() {
+        let root = fixture_vault(&[(
+            "note.md",
+            "\u{feff}---\r\ntags:\r\n  - a\r\n  - b\r\n---\r\nbody #c\r\n",
+        )]);
+        let tags = load_tags(&root.join("note.md")).unwrap();
+
+        assert_eq!(tags, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn read_paths_skips_missing_files_and_directories() {
+        let root = fixture_vault(&[("a.md", ""), ("b.txt", "")]);
+        let input = format!(
+            "{}\n\n{}\n{}\n{}\n",
+            root.join("a.md").display(),
+            root.display(),
+            root.join("missing.md").display(),
+            root.join("b.txt").display()
+        );
+        let paths = read_paths(input.as_bytes()).unwrap();
+
+        assert_eq!(paths, vec![root.join("a.md"), root.join("b.txt")]);
+    }
+
+    #[test]
+    fn respects_gitignore_when_asked() {
+        let root = fixture_vault(&[
+            (".gitignore", "archive/\n"),
+            (".ignore", "scratch.md\n"),
+            ("note.md", ""),
+            ("scratch.md", ""),
+            ("archive/old.md", ""),
+        ]);
+        let options = WalkOptions {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+        let mut all = collect_paths(&root).unwrap();
+        all.sort();
+        let kept = collect_paths_with(&root, &options).unwrap();
+
+        assert_eq!(
+            all,
+            vec![
+                root.join("archive/old.md"),
+                root.join("note.md"),
+                root.join("scratch.md")
+            ]
+        );
+        assert_eq!(kept, vec![root.join("note.md")]);
+    }
+
+    #[test]
+    fn ignore_file_is_always_read() {
+        for name in IGNORE_FILES {
+            let root = fixture_vault(&[
+                (name, "templates/\n"),
+                ("note.md", "---\ntags: [note]\n---\n"),
+                ("templates/daily.md", "---\ntags: [template]\n---\n"),
+            ]);
+            let paths = collect_paths(&root).unwrap();
+            let tags = collect_tags(&paths, ScanOptions::default()).unwrap();
+
+            assert_eq!(paths, vec![root.join("note.md")], "{}", name);
+            assert_eq!(tags, HashSet::from(["note".to_string()]));
+        }
+    }
+
+    #[test]
+    fn fold_case_joins_files() {
+        let mut tag_files = TagFiles::new();
+        for (tag, file) in [
+            ("project", "a.md"),
+            ("Project", "a.md"),
+            ("project", "b.md"),
+        ] {
+            tag_files
+                .entry(tag.to_string())
+                .or_default()
+                .insert(PathBuf::from(file));
+        }
+        tag_files.entry("タグ".to_string()).or_default();
+
+        let folded = fold_case_tag_files(tag_files);
+        assert_eq!(folded.keys().collect::<Vec<_>>(), ["Project", "タグ"]);
+        assert_eq!(folded["Project"].len(), 2);
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        assert!(has_prefix("project", "project"));
+        assert!(has_prefix("project/alpha", "project"));
+        assert!(has_prefix("project/alpha", "#project/"));
+        assert!(!has_prefix("projects", "project"));
+        assert!(!has_prefix("area/project", "project"));
+
+        let prefixes = ["project".to_string(), "area".to_string()];
+        let options = ScanOptions {
+            prefixes: &prefixes,
+            ..Default::default()
+        };
+        assert!(options.keeps("#area/home"));
+        assert!(!options.keeps("projects/x"));
+        assert!(ScanOptions::default().keeps("anything"));
+    }
+
+    #[test]
+    fn length_filters_ignore_the_hash() {
+        let options = ScanOptions {
+            min_length: 2,
+            max_length: Some(4),
+            ..Default::default()
+        };
+        assert!(!options.keeps("#1"));
+        assert!(options.keeps("#12"));
+        assert!(options.keeps("日本語"));
+        assert!(!options.keeps("#toolong"));
+    }
+
+    #[test]
+    fn strips_longest_prefix() {
+        let prefixes = ["status/".to_string(), "status/wip".to_string()];
+        assert_eq!(strip_tag_prefix("#status/done", &prefixes), "done");
+        assert_eq!(strip_tag_prefix("status/wip/today", &prefixes), "today");
+        assert_eq!(strip_tag_prefix("status", &prefixes), "status");
+        assert_eq!(
+            strip_tag_prefix("project/alpha", &prefixes),
+            "project/alpha"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_does_not_hide_front_matter() {
+        let root = fixture_vault(&[]);
+        let note = root.join("note.md");
+        std::fs::write(&note, b"---\ntags: [ok]\n---\nbinary \xff\xfe #inline\n").unwrap();
+        let without = root.join("plain.md");
+        std::fs::write(&without, b"no front matter \xff\n").unwrap();
+
+        let tags = load_tags(&note).unwrap();
+        let inline = inline_tags::extract_inline_tags(&note).unwrap();
+        let empty = load_tags(&without).unwrap();
+
+        assert_eq!(tags, vec!["ok"]);
+        assert_eq!(inline, vec!["inline"]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn file_tags_keep_untagged_files() {
+        let root = fixture_vault(&[
+            ("a.md", "---\ntags: [x, \"#y\"]\n---\n#x\n"),
+            ("b.md", "no tags here\n"),
+        ]);
+        let mut paths = collect_paths(&root).unwrap();
+        paths.sort();
+        let options = ScanOptions {
+            inline: true,
+            ..Default::default()
+        };
+
+        let file_tags = collect_file_tags(&paths, options);
+
+        assert_eq!(
+            file_tags,
+            vec![
+                (root.join("a.md"), vec!["x".to_string(), "y".to_string()]),
+                (root.join("b.md"), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_failures_are_recorded() {
+        let root = fixture_vault(&[
+            ("good.md", "---\ntags: [ok]\n---\n"),
+            ("bad.md", "---\ntags: 42\n---\n"),
+        ]);
+        let errors = ScanErrors::default();
+        let options = ScanOptions {
+            errors: Some(&errors),
+            ..Default::default()
+        };
+
+        let tags = collect_tags(&collect_paths(&root).unwrap(), options).unwrap();
+
+        assert_eq!(tags, HashSet::from(["ok".to_string()]));
+        let errors = errors.into_inner();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.ends_with("bad.md"));
+        assert!(matches!(errors[0].1, YamlError::InvalidTagsType));
+    }
+
+    #[test]
+    fn sort_modes() {
+        let tags = || {
+            vec![
+                ("ccc".to_string(), 1),
+                ("a".to_string(), 2),
+                ("bb".to_string(), 3),
+                ("b".to_string(), 2),
+            ]
+        };
+        let names = |sorted: Vec<(String, usize)>| -> Vec<String> {
+            sorted.into_iter().map(|(tag, _)| tag).collect()
+        };
+        assert_eq!(
+            names(sort_tags(tags(), SortMode::Alpha)),
+            ["a", "b", "bb", "ccc"]
+        );
+        assert_eq!(
+            names(sort_tags(tags(), SortMode::Freq)),
+            ["bb", "a", "b", "ccc"]
+        );
+        assert_eq!(
+            names(sort_tags(tags(), SortMode::Length)),
+            ["a", "b", "bb", "ccc"]
+        );
+        assert_eq!(
+            names(sort_tags(tags(), SortMode::Unsorted)),
+            ["ccc", "a", "bb", "b"]
+        );
+
+        let mixed = vec![
+            ("beta".to_string(), 1),
+            ("Alpha".to_string(), 1),
+            ("alpha".to_string(), 1),
+            ("Gamma".to_string(), 1),
+        ];
+        assert_eq!(
+            names(sort_tags(mixed.clone(), SortMode::Alpha)),
+            ["Alpha", "Gamma", "alpha", "beta"]
+        );
+        assert_eq!(
+            names(sort_tags(mixed, SortMode::AlphaCi)),
+            ["Alpha", "alpha", "beta", "Gamma"]
+        );
+    }
+
+    #[test]
+    fn parents_of_nested_tags() {
+        assert_eq!(
+            parent_tags("project/alpha/task").collect::<Vec<_>>(),
+            ["project", "project/alpha", "project/alpha/task"]
+        );
+        assert_eq!(parent_tags("solo").collect::<Vec<_>>(), ["solo"]);
+        assert_eq!(parent_tags("a//b").collect::<Vec<_>>(), ["a", "a//b"]);
+        assert_eq!(parent_tags("/a/").collect::<Vec<_>>(), ["/a", "/a/"]);
+
+        let ancestors = ancestor_tags(["project", "project/alpha/task", "proj"]);
+        assert_eq!(ancestors, HashSet::from(["project", "project/alpha"]));
+
+        let counts = TagCounts::from([("a/b".to_string(), 2), ("a".to_string(), 1)]);
+        assert_eq!(
+            expand_counts(counts),
+            TagCounts::from([("a".to_string(), 3), ("a/b".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn tags_as_array() {
+        let tags = parse_tags("---\ntags:\n  - a\n  - b/c\n---\n").unwrap();
+        assert_eq!(tags, vec!["a", "b/c"]);
+    }
+
+    #[test]
+    fn tags_as_comma_separated_string() {
+        let tags = parse_tags("---\ntags: foo, bar baz,,\n---\n").unwrap();
+        assert_eq!(tags, vec!["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn tags_as_scalar() {
+        assert_eq!(parse_tags("---\ntags: solo\n---\n").unwrap(), vec!["solo"]);
+        assert!(matches!(
+            parse_tags("---\ntags: 2024\n---\n"),
+            Err(YamlError::InvalidTagsType)
+        ));
+        assert!(matches!(
+            parse_tags("---\ntags: {a: b}\n---\n"),
+            Err(YamlError::InvalidTagsType)
+        ));
+    }
+
+    #[test]
+    fn tags_as_flow_array() {
+        assert_eq!(
+            parse_tags("---\ntags: [x, y]\n---\n").unwrap(),
+            vec!["x", "y"]
+        );
+    }
+
+    #[test]
+    fn singular_tag_key() {
+        assert_eq!(parse_tags("---\ntag: foo\n---\n").unwrap(), vec!["foo"]);
+        assert_eq!(
+            parse_tags("---\ntag: [a, b]\n---\n").unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            parse_tags("---\ntags: [a, b]\ntag: [b, c]\n---\n").unwrap(),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn malformed_tag_key_next_to_good_one() {
+        assert_eq!(
+            parse_tags("---\ntags: [a]\ntag: {x: y}\n---\n").unwrap(),
+            vec!["a"]
+        );
+        assert_eq!(
+            parse_tags("---\ntags: 42\ntag: b\n---\n").unwrap(),
+            vec!["b"]
+        );
+        assert!(matches!(
+            parse_tags("---\ntags: 42\ntag: 43\n---\n"),
+            Err(YamlError::InvalidTagsType)
+        ));
+    }
+
+    #[test]
+    fn aliases_next_to_tags() {
+        let data = parse_frontmatter("---\ntags: [a]\naliases: [My Note, Other]\n---\n").unwrap();
+        assert_eq!(data.tags, vec!["a"]);
+        assert_eq!(data.aliases, vec!["My Note", "Other"]);
+
+        let data = parse_frontmatter("---\naliases: First One, Second\n---\n").unwrap();
+        assert_eq!(data.aliases, vec!["First One", "Second"]);
+    }
+
+    #[test]
+    fn front_matter_fences() {
+        let split = |text: &str| split_front_matter(text.as_bytes(), false).unwrap();
+
+        let (section, range) = split("---\ntags: [a]\n...\nbody\n");
+        assert_eq!(section, "---\ntags: [a]\n---\n");
+        assert_eq!(range, 0..18);
+        assert_eq!(parse_tags(&section).unwrap(), vec!["a"]);
+
+        // a horizontal rule further down is not front matter
+        assert_eq!(
+            split("# Title\n\n---\ntags: [a]\n---\n"),
+            (String::new(), 0..0)
+        );
+        assert_eq!(split("---\n"), ("---\n".to_string(), 0..4));
+
+        let (section, range) = split("\n---\ntags: [b]\n---\n");
+        assert_eq!(section, "---\ntags: [b]\n---\n");
+        assert_eq!(range, 1..19);
+    }
+
+    #[test]
+    fn body_rules_are_not_front_matter() {
+        let root = fixture_vault(&[
+            ("plain.md", "Intro\n\n---\ntags: [ruled]\n---\n"),
+            (
+                "fronted.md",
+                "---\ntags: [front]\n---\nText\n\n---\ntags: [ruled]\n---\n",
+            ),
+        ]);
+        let plain = read_first_section(&root.join("plain.md")).unwrap();
+        let fronted = load_tags(&root.join("fronted.md")).unwrap();
+
+        assert!(plain.is_empty());
+        assert_eq!(fronted, vec!["front"]);
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        assert_eq!(
+            parse_tags("+++\ntitle = \"x\"\ntags = [\"a\", \"b/c\"]\n+++\n").unwrap(),
+            vec!["a", "b/c"]
+        );
+        assert_eq!(
+            parse_tags("+++\n[taxonomies]\ntags = [\"hugo\"]\n+++\n").unwrap(),
+            vec!["hugo"]
+        );
+        assert!(matches!(
+            parse_tags("+++\ntags = [\n+++\n"),
+            Err(YamlError::TomlError(_))
+        ));
+
+        let (section, range) =
+            split_front_matter(&b"+++\ntags = []\n+++\n#body\n"[..], false).unwrap();
+        assert_eq!(section, "+++\ntags = []\n+++\n");
+        assert_eq!(range, 0..18);
+        // further down, `+++` is just text
+        let (section, _) = split_front_matter(&b"text\n+++\n"[..], false).unwrap();
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn missing_tags_key() {
+        assert!(parse_tags("---\ntitle: note\n---\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn nested_tags_keys() {
+        let keys = ["meta.tags".to_string()];
+        assert_eq!(
+            parse_tags_keyed(
+                "---\ntags: [top]\nmeta:\n  tags:\n    - project/x\n---\n",
+                &keys
+            )
+            .unwrap(),
+            vec!["top", "project/x"]
+        );
+        // a scalar on the way is no `tags` key
+        assert_eq!(
+            parse_tags_keyed("---\ntags: [top]\nmeta: draft\n---\n", &keys).unwrap(),
+            vec!["top"]
+        );
+        assert!(parse_tags_keyed("---\ntitle: note\n---\n", &keys)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn empty_and_null_tags() {
+        assert!(parse_tags("---\ntags: \"\"\n---\n").unwrap().is_empty());
+        assert!(parse_tags("---\ntags:\n---\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn counts_merge_hashed_and_plain_tags() {
+        let mut counts = TagCounts::new();
+        add_counts(
+            &mut counts,
+            ["#foo", "foo", "bar", "#baz", "baz"].map(String::from),
+        );
+        add_counts(&mut counts, ["#foo"].map(String::from));
+        assert_eq!(
+            sorted_counts(&counts),
+            vec![("foo", 2), ("bar", 1), ("baz", 1)]
+        );
+    }
+
+    #[test]
+    fn counts_notes_per_tag() {
+        let root = fixture_vault(&[
+            ("a.md", "---\ntags: [foo, foo]\n---\n#foo and #bar\n"),
+            ("b.md", "---\ntags: foo\n---\n#bar #bar\n"),
+        ]);
+
+        let options = ScanOptions {
+            inline: true,
+            ..Default::default()
+        };
+        let counts = count_tags(&collect_paths(&root).unwrap(), options);
+
+        assert_eq!(sorted_counts(&counts), vec![("bar", 2), ("foo", 2)]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Checks tags against the characters Obsidian accepts.
+
+use thiserror::Error;
+
+use crate::output::TagFiles;
+use crate::remove_hash;
+
+/// Why Obsidian would not accept a tag.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TagLintError {
+    #[error("empty tag")]
+    Empty,
+    #[error("only digits, a tag needs at least one other character")]
+    NumericOnly,
+    #[error("contains whitespace")]
+    Whitespace,
+    #[error("contains {0:?}, only letters, digits, '_', '-' and '/' are allowed")]
+    InvalidCharacter(char),
+    #[error("empty segment between '/'")]
+    EmptySegment,
+}
+
+/// Whether `tag` (with or without its `#`) is a valid Obsidian tag: letters
+/// of any script, digits, `_`, `-` and `/`, with at least one character
+/// that isn't a digit.
+pub fn validate_tag(tag: &str) -> Result<(), TagLintError> {
+    let tag = remove_hash(tag);
+    if tag.is_empty() {
+        return Err(TagLintError::Empty);
+    }
+    if let Some(c) = tag.chars().find(|c| !is_tag_char(*c)) {
+        return Err(if c.is_whitespace() {
+            TagLintError::Whitespace
+        } else {
+            TagLintError::InvalidCharacter(c)
+        });
+    }
+    if tag.split('/').any(str::is_empty) {
+        return Err(TagLintError::EmptySegment);
+    }
+    if tag.chars().all(|c| c.is_ascii_digit() || c == '/') {
+        return Err(TagLintError::NumericOnly);
+    }
+    Ok(())
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+/// The invalid tags of `tag_files`, with why, in tag order.
+pub fn lint_tags(tag_files: &TagFiles) -> Vec<(&str, TagLintError)> {
+    tag_files
+        .keys()
+        .filter_map(|tag| validate_tag(tag).err().map(|e| (tag.as_str(), e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_tags() {
+        for tag in [
+            "project",
+            "#project/alpha",
+            "2024-review",
+            "y2024",
+            "日本語",
+            "a_b",
+        ] {
+            assert_eq!(validate_tag(tag), Ok(()), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn numeric_only() {
+        assert_eq!(validate_tag("2024"), Err(TagLintError::NumericOnly));
+        assert_eq!(validate_tag("#2024/01"), Err(TagLintError::NumericOnly));
+    }
+
+    #[test]
+    fn embedded_space() {
+        assert_eq!(validate_tag("two words"), Err(TagLintError::Whitespace));
+    }
+
+    #[test]
+    fn emoji_and_punctuation() {
+        assert_eq!(
+            validate_tag("idea💡"),
+            Err(TagLintError::InvalidCharacter('💡'))
+        );
+        assert_eq!(
+            validate_tag("done."),
+            Err(TagLintError::InvalidCharacter('.'))
+        );
+        assert_eq!(validate_tag("a//b"), Err(TagLintError::EmptySegment));
+        assert_eq!(validate_tag("#"), Err(TagLintError::Empty));
+    }
+}
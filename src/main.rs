@@ -1,191 +1,1409 @@
 use anyhow::{anyhow, Context};
-use clap::{arg, command, Parser};
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
+use clap_complete::Shell;
 use dotenvy::dotenv;
 use expanduser::expanduser;
-use log::error;
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
+use notify::{RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Mutex};
+use std::time::SystemTime;
 use std::{
     env::{self},
     path::{Path, PathBuf},
 };
-use thiserror::Error;
-use walkdir::WalkDir;
 
-use std::process::{Command, Stdio};
+use obsidian_get_tags::cache::{default_cache_path, TagCache};
+use obsidian_get_tags::config::Config;
+use obsidian_get_tags::inline_tags::{TagPattern, TAG_BODY};
+use obsidian_get_tags::lint::lint_tags;
+use obsidian_get_tags::output::{
+    write_error_report, write_grouped, FileTags, OutputFormat, TagCount, TagFile, TagFiles,
+    TagLint, TagReport,
+};
+use obsidian_get_tags::tree::TagTree;
+use obsidian_get_tags::watch::{TagIndex, WatchFormat};
+use obsidian_get_tags::{
+    ancestor_tags, build_globset, cmp_ignore_case, collect_aliases, collect_file_tags,
+    collect_inline_tag_files, collect_inline_tags, collect_paths_with, collect_tag_files,
+    collect_tags, expand_tag_files, fold_case, fold_case_tag_files, has_extension, has_prefix,
+    is_collected, parent_tags, parse_time, read_paths_from, relative_path, remove_hash, sort_tags,
+    stream_tags, ScanErrors, ScanOptions, SortMode, VaultError, WalkOptions, DEFAULT_EXCLUDES,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(about = "Get Tags from vault")]
+#[command(
+    after_help = "Exit status: 0 on success; 1 when the tags fail a check (--strict, \
+--lint, --fail-if-empty) or another error occurs; 2 on a usage error or a vault path that \
+is missing or not a directory"
+)]
+struct Args {
+    /// Path to the Obsidian vault; repeat it to merge several vaults. Falls
+    /// back to `OBSIDIAN_VAULT_PATH` (a `:`-separated list, `;` on Windows),
+    /// then to `vault_path` in the config file
+    #[arg(short, long, value_hint = ValueHint::DirPath)]
+    path: Vec<PathBuf>,
+
+    /// Read the files to scan from stdin, one path per line (NUL-separated
+    /// with --null), instead of walking the vault (same as `--path -`).
+    /// Relative paths are taken from the vault, not the current directory,
+    /// so list them from its root (`cd vault && fd -e md | ...`); with
+    /// several vaults they are taken from the current directory. Files
+    /// without a note --extension are skipped
+    #[arg(long, visible_alias = "files-from-stdin")]
+    stdin: bool,
+
+    /// Read the --stdin paths NUL-separated, as from `find -print0` or
+    /// `fd -0`
+    #[arg(short = 'z', long)]
+    null: bool,
+
+    /// Config file to read instead of
+    /// `~/.config/obsidian-get-tags/config.toml`
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    config: Option<String>,
+
+    /// Also scan directories starting with `.`, such as `.obsidian` and `.trash`
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Skip vault-relative paths matching this glob, a directory (`Archive`,
+    /// `Archive/**`) or files (`**/*.draft.md`); repeatable
+    #[arg(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only scan vault-relative paths matching this glob (e.g. `Projects/**`);
+    /// repeatable. --exclude is applied first and wins over it
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Don't exclude `.obsidian/**` and `.trash/**` (only matters with
+    /// --include-hidden)
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// Extension of the notes to scan, case-insensitive; repeatable
+    #[arg(long = "extension", value_name = "EXT", default_value = "md")]
+    extensions: Vec<String>,
+
+    /// Only walk this many levels: 1 scans the notes in the vault root, 2
+    /// those of its folders too, and so on (`find -maxdepth` counts the
+    /// same). 0 scans nothing. Unlimited by default
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Walk into symlinked folders too. Symlinked notes are always scanned
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Only scan notes modified since then: a duration (`7d`, `24h`), a date
+    /// (`2024-06-01`) or a timestamp (`2024-06-01T12:00:00Z`)
+    #[arg(long, value_name = "WHEN", value_parser = parse_time)]
+    since: Option<SystemTime>,
+
+    /// Only scan notes modified before then, same forms as --since
+    #[arg(long, value_name = "WHEN", value_parser = parse_time)]
+    until: Option<SystemTime>,
+
+    /// Skip files ignored by `.gitignore`, `.ignore` or the global git
+    /// excludes. A `.get-tags-ignore` or `.obsidian-get-tags-ignore` file at
+    /// the vault root is read even without it, and wins over them
+    #[arg(long, visible_alias = "use-gitignore")]
+    respect_gitignore: bool,
+
+    /// Also collect inline `#tags` from note bodies (via rg, or the built-in
+    /// scanner when rg is not installed)
+    #[arg(short, long, value_name = "in_content", conflicts_with = "inline")]
+    rg: bool,
+
+    /// Also collect inline `#tags` with the built-in scanner, in the same pass
+    /// as front matter
+    #[arg(short, long)]
+    inline: bool,
+
+    /// Regex matching an inline tag, `#` included, for both rg and the
+    /// built-in scanner. A tag must also start a line or follow whitespace.
+    /// Lookaround is not supported
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = TagPattern::new,
+        default_value = TAG_BODY,
+    )]
+    tag_pattern: TagPattern,
+
+    /// Also take inline tags from fenced code blocks and `code` spans, where
+    /// `#include` and `#!/bin/sh` are usually not tags. rg (`--rg`) can't tell
+    /// code apart and always does
+    #[arg(long)]
+    include_code_tags: bool,
+
+    /// Also look for inline tags in Excalidraw drawings, and scan `.canvas`
+    /// files when their extension is listed. Drawings are JSON scenes whose
+    /// colors (`#ffffff`) would otherwise come out as tags
+    #[arg(long)]
+    no_skip_excalidraw: bool,
+
+    /// Only read front matter, the default. Overrides an earlier `--rg` or
+    /// `--inline`
+    #[arg(long, overrides_with_all = ["rg", "inline"])]
+    no_inline: bool,
+
+    /// Also read properties written without `---` delimiters at the top of a
+    /// note, as some exporters do. A note starting with `Key: value` Markdown
+    /// is then mistaken for properties
+    #[arg(long)]
+    legacy_props: bool,
+
+    /// Also read tags from this front matter key, a dotted path for nested
+    /// ones like `meta.tags`; repeatable. `tags` and `tag` are always read
+    #[arg(long, value_name = "DOTTED.PATH")]
+    tags_key: Vec<String>,
+
+    /// Parse every note without reading or writing the cache of an earlier
+    /// --cache or --index, e.g. one from a shell alias
+    #[arg(long, overrides_with_all = ["cache", "index"])]
+    no_cache: bool,
+
+    /// Remember the tags of each note in this file, to only parse the notes
+    /// changed since; `~/.cache/obsidian-get-tags/cache.json` when no path
+    /// is given. Deleting it clears the cache
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        value_hint = ValueHint::FilePath,
+        overrides_with = "no_cache"
+    )]
+    cache: Option<Option<PathBuf>>,
+
+    /// Keep the cache in a SQLite database at this path instead, with a row
+    /// per note; only the rows of changed notes are written back
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "cache",
+        overrides_with = "no_cache"
+    )]
+    index: Option<PathBuf>,
+
+    /// Parse every note again and rewrite the rows of the vaults in the
+    /// `--index`
+    #[arg(long, requires = "index")]
+    rebuild_index: bool,
+
+    /// Print tags with their leading `#`, ready for an Obsidian search
+    #[arg(long, overrides_with = "no_hash", conflicts_with = "watch")]
+    with_hash: bool,
+
+    /// Print tags without `#` (the default)
+    #[arg(long, overrides_with = "with_hash")]
+    no_hash: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    format: OutputFormat,
+
+    /// Write the output to this file instead of stdout, creating its parent
+    /// directories as needed
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Add to the end of the --output file instead of replacing it
+    #[arg(long, requires = "output")]
+    append: bool,
+
+    /// End each entry of plain output with NUL instead of a newline, for
+    /// `xargs -0`
+    #[arg(
+        short = '0',
+        long,
+        conflicts_with_all = ["delimiter", "count", "tree", "files", "per_file", "aliases"]
+    )]
+    print0: bool,
+
+    /// End each entry of plain output with this character instead of a newline
+    #[arg(
+        long,
+        value_name = "CHAR",
+        conflicts_with_all = ["count", "tree", "files", "per_file", "aliases"]
+    )]
+    delimiter: Option<char>,
+
+    /// Print in how many notes each tag is used, most used first
+    #[arg(short, long, conflicts_with = "files")]
+    count: bool,
+
+    /// Order of the output [default: alpha, or freq with --count]
+    #[arg(long, value_enum, value_name = "MODE")]
+    sort: Option<SortMode>,
+
+    /// Invert the sort order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Print each tag as soon as it is found rather than once every note is
+    /// scanned, in no particular order. Only for a plain list of tags;
+    /// implied there by `--sort none`
+    #[arg(long)]
+    stream: bool,
+
+    /// Only output `PREFIX` and the tags nested under it (`project` keeps
+    /// `project/alpha` but not `projects`); repeatable
+    #[arg(long, visible_alias = "tag-prefix", value_name = "PREFIX")]
+    prefix: Vec<String>,
+
+    /// Print the tags without the --prefix they are under: `status/done`
+    /// becomes `done`. Trees keep their full nesting
+    #[arg(long, requires = "prefix")]
+    tag_prefix_strip: bool,
+
+    /// Leave out tags shorter than N characters, not counting `#`
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    tag_length_min: usize,
+
+    /// Leave out tags longer than N characters, not counting `#`
+    #[arg(long, value_name = "N")]
+    tag_length_max: Option<usize>,
+
+    /// Leave out tags used in fewer than N notes (front matter or inline)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = parse_min_count,
+        conflicts_with_all = ["files", "per_file", "aliases", "files_for_tag"]
+    )]
+    min_count: usize,
+
+    /// Leave out tags used in more than N notes
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["files", "per_file", "aliases", "files_for_tag"]
+    )]
+    max_count: Option<usize>,
+
+    /// Only print the N most used tags
+    #[arg(long, value_name = "N", requires = "count")]
+    top: Option<usize>,
+
+    /// Also output every ancestor of nested tags (`a/b/c` adds `a` and `a/b`);
+    /// with --count, children count toward their ancestors
+    #[arg(long, visible_alias = "expand-nested")]
+    expand_parents: bool,
+
+    /// Leave out tags that are an ancestor of another found tag
+    /// (`a` and `a/b` when `a/b/c` is there)
+    #[arg(long, conflicts_with_all = ["expand_parents", "tree"])]
+    leaf_only: bool,
+
+    /// Draw nested tags (`a/b/c`) as a tree, with counts when combined with
+    /// --count, and subtags ordered by --sort and --reverse. A count includes
+    /// the subtags', as under `_count` in YAML
+    #[arg(long, conflicts_with = "files")]
+    tree: bool,
+
+    /// List the files carrying each tag, as `tag: a.md, b.md` lines (or
+    /// `{ "tag", "files" }` objects in JSON)
+    #[arg(long, visible_alias = "with-files")]
+    files: bool,
+
+    /// Also list the `aliases` of every note, after the tags (prefixed with
+    /// `alias: ` in plain output)
+    #[arg(long, conflicts_with_all = ["count", "tree", "files", "sort"])]
+    aliases: bool,
+
+    /// Print each tagged note followed by its tags, as `note.md: a, b` lines
+    /// (or `{ "file", "tags" }` objects in JSON)
+    #[arg(long, conflicts_with_all = ["count", "tree", "files", "sort", "aliases"])]
+    per_file: bool,
+
+    /// With --per-file, also list the notes without any tag
+    #[arg(long, requires = "per_file")]
+    include_empty: bool,
+
+    /// Instead of the tags, list the ones Obsidian would reject (digits only,
+    /// spaces, punctuation, ...) with the files using them, and exit with
+    /// status 1 if there are any
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "tree", "files", "per_file", "aliases", "files_for_tag"]
+    )]
+    lint: bool,
 
-use anyhow::Result;
+    /// Only list the tags used by a single note, as `tag<TAB>file` lines, to
+    /// spot typos and one-off tags
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "tree", "files", "per_file", "aliases", "files_for_tag", "lint"]
+    )]
+    singleton_tags: bool,
 
-#[derive(Error, Debug)]
-enum YamlError {
-    #[error("Expected 'tags' to be an array, but found a different type")]
-    InvalidTagsType,
-    #[error("Failed to parse YAML front matter: {0}")]
-    ParseError(#[from] yaml_rust::ScanError),
-    #[error("Failed to load file: {0}")]
-    LoadError(#[from] std::io::Error),
+    /// Report each vault separately: plain lines start with `vault: `, CSV
+    /// gets a `vault` column and JSON becomes an object keyed by vault, where
+    /// a vault is named after its last path component
+    #[arg(long, conflicts_with = "stdin")]
+    group_by_vault: bool,
+
+    /// List the notes carrying a tag matching this glob (e.g. `project/*`),
+    /// in front matter or inline (read by rg with --rg); case is ignored
+    /// with --case-insensitive
+    #[arg(long, value_name = "TAG", conflicts_with_all = ["count", "tree", "files"])]
+    files_for_tag: Option<String>,
+
+    /// Exit with status 1 when no tag is found
+    #[arg(long)]
+    fail_if_empty: bool,
+
+    /// Only list the notes that would be scanned, relative to the vault, to
+    /// check --exclude and friends. No tag is read
+    #[arg(long, conflicts_with = "watch")]
+    dry_run: bool,
+
+    /// Print the paths of notes canonical and absolute instead of relative
+    /// to the vault
+    #[arg(long)]
+    absolute: bool,
+
+    /// Print the paths of notes relative to this folder instead of the
+    /// vault; notes outside of it are printed absolute
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        conflicts_with = "absolute"
+    )]
+    relative_to: Option<PathBuf>,
+
+    /// Treat tags differing only by case as one, printed with the casing that
+    /// sorts first; with --count their counts are added up
+    #[arg(
+        long,
+        visible_aliases = ["fold-case", "ignore-case"],
+        conflicts_with_all = ["files", "per_file"]
+    )]
+    case_insensitive: bool,
+
+    /// Same as --case-insensitive, printing every tag in lowercase instead
+    #[arg(long, conflicts_with_all = ["files", "per_file"])]
+    lowercase: bool,
+
+    /// Keep running and print `+tag`/`-tag` lines as notes change, after the
+    /// tags found at startup. Inline tags use the built-in scanner
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "tree", "files", "per_file", "aliases", "files_for_tag", "stdin"]
+    )]
+    watch: bool,
+
+    /// How --watch prints changes
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = WatchFormat::Plain, requires = "watch")]
+    watch_format: WatchFormat,
+
+    /// Don't draw a progress bar on stderr while scanning. It is only drawn
+    /// when stdout is a terminal and the format isn't JSON anyway
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Scan with this many threads; 0 uses one per core
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Log progress information on stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// After the output, print a table of the files whose front matter could
+    /// not be parsed on stderr
+    #[arg(long)]
+    report_errors: bool,
+
+    /// Exit with status 1 when any front matter could not be parsed, after
+    /// scanning everything else
+    #[arg(long)]
+    strict: bool,
+
+    /// Print each file whose front matter could not be parsed on stderr,
+    /// even when `RUST_LOG` hides the log, and carry on
+    #[arg(long)]
+    warn_errors: bool,
+
+    /// Print the completion script of this shell and exit, e.g.
+    /// `--generate-completions zsh > ~/.zfunc/_obsidian-get-tags`
+    #[arg(long, value_name = "SHELL")]
+    generate_completions: Option<Shell>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn read_first_section(path: &Path) -> Result<String, YamlError> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+/// What to print; the options above come before it.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the tags of the vault (the default)
+    #[command(visible_alias = "tags")]
+    List,
+    /// List the notes carrying any of the tags, or their subtags
+    #[command(visible_alias = "files")]
+    Notes {
+        /// Tags to look for, with or without `#`
+        #[arg(required = true, value_name = "TAG")]
+        tags: Vec<String>,
+        /// Only list the notes carrying all of the tags
+        #[arg(long)]
+        all: bool,
+        /// Don't match subtags: `project` no longer finds `project/alpha`
+        #[arg(long)]
+        exact: bool,
+    },
+    /// Print the completion script of a shell, like --generate-completions
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: Shell,
+    },
+    /// Compare the tags of two vaults: `-tag` is only in the first, `+tag`
+    /// only in the second, and ` tag` in both. --path is not used
+    Diff {
+        #[arg(value_name = "VAULT_A", value_hint = ValueHint::DirPath)]
+        old: PathBuf,
+        #[arg(value_name = "VAULT_B", value_hint = ValueHint::DirPath)]
+        new: PathBuf,
+        /// List the notes carrying each tag found in one vault only
+        #[arg(long)]
+        diff_per_file: bool,
+    },
+}
 
-    let mut in_section = false;
-    let mut current_section = String::new();
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        // like clap's usage errors
+        let code = if e.is::<VaultError>() { 2 } else { 1 };
+        std::process::exit(code);
+    }
+}
 
-    for line in reader.lines() {
-        let line = line?;
+fn run() -> anyhow::Result<()> {
+    dotenv().ok();
 
-        if line.trim() == "---" {
-            if in_section {
-                // End of the section, append "---" and return the result
-                current_section.push_str("---\n");
-                return Ok(current_section);
-            } else {
-                // Start a new section, append "---"
-                in_section = true;
-                current_section.push_str("---\n");
+    let mut args = Args::parse();
+    let shell = match &args.command {
+        Some(Command::Completions { shell }) => Some(*shell),
+        _ => args.generate_completions,
+    };
+    if let Some(shell) = shell {
+        let mut command = Args::command();
+        clap_complete::generate(
+            shell,
+            &mut command,
+            env!("CARGO_BIN_NAME"),
+            &mut io::stdout(),
+        );
+        return Ok(());
+    }
+    args.case_insensitive |= args.lowercase;
+    // the notes are canonicalized to be compared with it
+    if let Some(base) = &args.relative_to {
+        let base = expand_home(base.clone())?;
+        args.relative_to =
+            Some(fs::canonicalize(&base).with_context(|| base.display().to_string())?);
+    }
+
+    let level = if args.verbose { "info" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
+
+    // `--path -` is a shorthand for --stdin
+    let stdin = args.stdin || args.path.iter().any(|path| path == Path::new("-"));
+    let paths: Vec<PathBuf> = args
+        .path
+        .iter()
+        .filter(|path| *path != Path::new("-"))
+        .cloned()
+        .collect();
+
+    let mut excludes = args.exclude.clone();
+    if !args.no_default_excludes {
+        excludes.extend(DEFAULT_EXCLUDES.iter().map(|glob| glob.to_string()));
+    }
+    let walk = WalkOptions {
+        include_hidden: args.include_hidden,
+        exclude: build_globset(&excludes)?,
+        include: if args.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(&args.include)?)
+        },
+        respect_gitignore: args.respect_gitignore,
+        extensions: args.extensions.clone(),
+        include_canvas: args.no_skip_excalidraw,
+        max_depth: args.max_depth,
+        follow_symlinks: args.follow_symlinks,
+        since: args.since,
+        until: args.until,
+    };
+    // 0 is rayon's own default, all cores, on the global pool
+    let pool = match args.threads {
+        0 => None,
+        threads => Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?,
+        ),
+    };
+    if let Some(Command::Diff {
+        old,
+        new,
+        diff_per_file,
+    }) = &args.command
+    {
+        let (old, new) = (expand_home(old.clone())?, expand_home(new.clone())?);
+        return install(pool.as_ref(), || {
+            diff(&old, &new, &walk, &args, *diff_per_file)
+        });
+    }
+
+    // --path, then OBSIDIAN_VAULT_PATH, then the config file
+    let vaults: Vec<PathBuf> = if !paths.is_empty() {
+        paths
+    } else if let Some(paths) = env::var_os("OBSIDIAN_VAULT_PATH") {
+        env::split_paths(&paths)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    } else {
+        let config_path = match &args.config {
+            Some(path) => Some(expanduser(path)?),
+            None => Config::default_path(),
+        };
+        config_path
+            .map(|path| Config::load(&path))
+            .transpose()?
+            .and_then(|config| config.vault_path)
+            .map(PathBuf::from)
+            .into_iter()
+            .collect()
+    };
+    let vaults = match vaults {
+        // listed files are reported relative to the working directory
+        vaults if vaults.is_empty() && stdin => vec![PathBuf::from(".")],
+        vaults if vaults.is_empty() => {
+            return Err(anyhow!(
+                "no vault given: pass --path, set OBSIDIAN_VAULT_PATH or add vault_path to the config file"
+            ))
+        }
+        vaults => dedup_vaults(
+            vaults
+                .into_iter()
+                .map(expand_home)
+                .collect::<io::Result<_>>()?,
+        ),
+    };
+    // reported paths are relative to the vault, or kept whole when there are
+    // several since they could clash
+    let vault_path = match vaults.as_slice() {
+        [vault] => vault.clone(),
+        _ => PathBuf::new(),
+    };
+
+    if args.watch {
+        if args.command.is_some() {
+            return Err(anyhow!("--watch only lists tags"));
+        }
+        let [vault] = vaults.as_slice() else {
+            return Err(anyhow!("--watch takes a single vault"));
+        };
+        return watch(vault, &walk, &args);
+    }
+    let files = if stdin {
+        let separator = if args.null { b'\0' } else { b'\n' };
+        let mut files = read_paths_from(io::stdin().lock(), separator, &vault_path)?;
+        files.retain(|path| {
+            let note = has_extension(path, &walk);
+            if !note {
+                warn!("{}: not a note, skipping it", path.display());
+            }
+            note
+        });
+        files
+    } else {
+        let mut files: Vec<PathBuf> = vaults
+            .iter()
+            .map(|vault| collect_paths_with(vault, &walk))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        // a vault nested in another one is walked twice
+        files.sort_unstable();
+        files.dedup();
+        files
+    };
+    for vault in &vaults {
+        info!("scanning {}", vault.display());
+    }
+    info!("{} files to scan", files.len());
+
+    // notes listed on stdin may live anywhere, so only cache whole vaults
+    let cached = !(args.no_cache || args.dry_run || stdin || args.files_for_tag.is_some());
+    let cache = match (&args.index, &args.cache) {
+        _ if !cached => None,
+        (Some(index), _) => {
+            let path = expand_home(index.clone())?;
+            Some(TagCache::load_index(&path, &vaults, args.rebuild_index))
+        }
+        (None, Some(path)) => {
+            let path = match path {
+                Some(path) => expand_home(path.clone())?,
+                None => default_cache_path().context("no cache directory on this system")?,
+            };
+            Some(TagCache::load_from(&path, &vaults))
+        }
+        (None, None) => None,
+    };
+    if args.stream && !streamable(&args) {
+        return Err(anyhow!(
+            "--stream only prints a plain list of tags, unsorted and unfiltered by count"
+        ));
+    }
+    let stream = args.stream || (args.sort == Some(SortMode::Unsorted) && streamable(&args));
+    let errors = ScanErrors::default();
+    // a bar would be drawn over the tags streamed to the terminal
+    let show_progress = !args.no_progress
+        && !args.dry_run
+        && !stream
+        && io::stdout().is_terminal()
+        && !matches!(args.format, OutputFormat::Json | OutputFormat::JsonCompact);
+    let progress = show_progress.then(|| {
+        ProgressBar::new(files.len() as u64).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} notes {elapsed}")
+                .expect("valid progress template"),
+        )
+    });
+    let rg_failed = AtomicBool::new(false);
+    let options = ScanOptions {
+        inline: args.inline,
+        errors: Some(&errors),
+        prefixes: &args.prefix,
+        cache: cache.as_ref(),
+        legacy_props: args.legacy_props,
+        tags_keys: &args.tags_key,
+        tag_pattern: Some(&args.tag_pattern),
+        scan_drawings: args.no_skip_excalidraw,
+        include_code: args.include_code_tags,
+        progress: progress.as_ref(),
+        rg_failed: Some(&rg_failed),
+        min_length: args.tag_length_min,
+        max_length: args.tag_length_max,
+    };
+
+    let sort = args.sort.unwrap_or(if args.count {
+        SortMode::Freq
+    } else {
+        SortMode::Alpha
+    });
+
+    let separator = if args.print0 {
+        Some('\0')
+    } else {
+        args.delimiter
+    };
+    if separator.is_some() && args.format != OutputFormat::Plain {
+        return Err(anyhow!("--print0 and --delimiter need --format plain"));
+    }
+    let mut out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(create_output(path, args.append)?),
+        None => Box::new(io::stdout()),
+    };
+    if args.dry_run {
+        let paths = files
+            .iter()
+            .map(|path| relative_path(&vault_path, path))
+            .collect();
+        let mut report = TagReport::Paths(paths);
+        if args.absolute || args.relative_to.is_some() {
+            report.map_paths(|path| shown_path(&args, &vault_path, path));
+        }
+        match separator {
+            Some(separator) => report.write_separated(separator, &mut out)?,
+            None => report.write(args.format, &mut out)?,
+        }
+        out.flush()?;
+        return Ok(());
+    }
+    let mut invalid_tags = 0;
+    let mut collected = false;
+    if stream {
+        let out = Mutex::new(&mut out);
+        let hash = if args.with_hash { "#" } else { "" };
+        let end = separator.unwrap_or('\n');
+        // whole lines at once, so that the workers' tags don't interleave
+        let emit = |tag: &str| -> io::Result<()> {
+            let line = format!("{}{}{}", hash, tag, end);
+            out.lock()
+                .expect("output poisoned")
+                .write_all(line.as_bytes())
+        };
+        let mut found = install(pool.as_ref(), || stream_tags(&files, options, emit))?;
+        if args.rg {
+            for tag in collect_inline_tags(&files, options)? {
+                let tag = remove_hash(&tag);
+                if options.keeps(tag) && found.insert(tag.to_string()) {
+                    emit(tag)?;
+                }
             }
-        } else if in_section {
-            // Buffer lines in the current section
-            current_section.push_str(&line);
-            current_section.push('\n');
+        }
+        collected |= !found.is_empty();
+    } else if args.group_by_vault {
+        if stdin {
+            return Err(anyhow!(
+                "--group-by-vault needs vaults to walk, not a list of files"
+            ));
+        }
+        if separator.is_some() {
+            return Err(anyhow!(
+                "--group-by-vault can't be combined with --print0 or --delimiter"
+            ));
+        }
+        let mut reports = Vec::new();
+        for vault in &vaults {
+            let files: Vec<PathBuf> = files
+                .iter()
+                .filter(|path| path.starts_with(vault))
+                .cloned()
+                .collect();
+            let report = install(pool.as_ref(), || {
+                build_report(&args, vault, &files, options, sort)
+            })?;
+            invalid_tags += lint_count(&report);
+            collected |= !report.is_empty();
+            reports.push((vault_name(vault), report));
+        }
+        progress.iter().for_each(ProgressBar::finish_and_clear);
+        write_grouped(&reports, args.format, &mut out)?;
+    } else {
+        let report = install(pool.as_ref(), || {
+            build_report(&args, &vault_path, &files, options, sort)
+        })?;
+        invalid_tags += lint_count(&report);
+        collected |= !report.is_empty();
+        progress.iter().for_each(ProgressBar::finish_and_clear);
+        match separator {
+            Some(separator) => report.write_separated(separator, &mut out)?,
+            None => report.write(args.format, &mut out)?,
         }
     }
+    out.flush()?;
+    drop(out);
 
-    // If we reach the end of the file but no closing `---` is found, return the buffered content.
-    if in_section {
-        return Ok(current_section);
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save() {
+            warn!("could not save the cache: {}", e);
+        }
     }
 
-    // If no section is found, return an empty string.
-    Ok(String::new())
-}
+    let mut errors = errors.into_inner();
+    for (path, _) in &mut errors {
+        *path = relative_path(&vault_path, path);
+    }
+    errors.sort_by(|a, b| a.0.cmp(&b.0));
+    if args.report_errors && !errors.is_empty() {
+        write_error_report(&errors, &mut io::stderr().lock())?;
+    }
+    // the errors may be filtered out of the log by RUST_LOG, so repeat them here
+    if (args.strict || args.warn_errors) && !args.report_errors {
+        for (path, error) in &errors {
+            eprintln!("{}: {}", path.display(), error);
+        }
+    }
+    if args.strict && !errors.is_empty() {
+        eprintln!("{} file(s) could not be parsed", errors.len());
+        std::process::exit(1);
+    }
+    if invalid_tags > 0 {
+        eprintln!("{} invalid tag(s)", invalid_tags);
+        std::process::exit(1);
+    }
+    // a failing rg is only fatal when there is nothing else to show
+    if rg_failed.into_inner() && !collected {
+        eprintln!("rg failed and no tags were found");
+        std::process::exit(1);
+    }
+    if args.fail_if_empty && !collected {
+        eprintln!("no tags found");
+        std::process::exit(1);
+    }
 
-type Tags = Vec<String>;
+    Ok(())
+}
 
-fn load_tags(path: &Path) -> Result<Tags, YamlError> {
-    let content = read_first_section(path)?;
-    let items = frontmatter::parse(&content).map_err(YamlError::ParseError)?;
-    let make_tag = |s: &str| -> Option<String> {
-        let s = s.trim();
-        if !s.is_empty() {
-            Some(String::from(s))
+/// The notes of `tag_files` carrying any (or `all`) of `tags`, each matching
+/// its subtags too unless `exact`.
+fn notes_tagged(
+    tag_files: &TagFiles,
+    tags: &[String],
+    all: bool,
+    exact: bool,
+    case_insensitive: bool,
+) -> Vec<PathBuf> {
+    let normalize = |tag: &str| {
+        if case_insensitive {
+            tag.to_lowercase()
         } else {
-            None
+            tag.to_string()
         }
     };
-    match items {
-        None => Ok(Vec::new()),
-        Some(yaml) => match yaml["tags"].as_vec() {
-            Some(tags) => Ok(tags
-                .iter()
-                .filter_map(|tag| tag.as_str().and_then(make_tag))
-                .collect()),
-            None => Err(YamlError::InvalidTagsType),
-        },
+    let notes_of = |wanted: &str| -> BTreeSet<&PathBuf> {
+        let wanted = normalize(remove_hash(wanted));
+        tag_files
+            .iter()
+            .filter(|(tag, _)| {
+                let tag = normalize(tag);
+                if exact {
+                    tag == wanted
+                } else {
+                    has_prefix(&tag, &wanted)
+                }
+            })
+            .flat_map(|(_, files)| files)
+            .collect()
+    };
+    let mut found = tags.iter().map(|tag| notes_of(tag));
+    let first = found.next().unwrap_or_default();
+    let notes = found.fold(first, |acc, notes| {
+        if all {
+            acc.intersection(&notes).copied().collect()
+        } else {
+            acc.union(&notes).copied().collect()
+        }
+    });
+    notes.into_iter().cloned().collect()
+}
+
+/// A `--min-count`: every tag is used at least once, so 0 is a mistake.
+fn parse_min_count(arg: &str) -> Result<usize, String> {
+    match arg.parse::<usize>().map_err(|e| e.to_string())? {
+        0 => Err("must be at least 1, which already keeps every tag".to_string()),
+        count => Ok(count),
     }
 }
 
-/// Obsidianタグを収集するイテレータを返す関数
-///
-/// # Arguments
-/// * `directory` - タグを検索するディレクトリパス
-///
-/// # Returns
-/// タグの文字列イテレータ
-fn collect_obsidian_tags(
-    directory: &str,
-) -> anyhow::Result<impl Iterator<Item = Result<String, std::io::Error>>> {
-    let command = Command::new("rg")
-        .arg("--pcre2")
-        .arg("-o")
-        .arg(r#"(?<=\s)#[^\s\#\|\(\)\[\]\"\']+(?:\/[^\s\#\|\(\)\[\]\"\']+)*"#)
-        .arg("--no-filename")
-        .arg(directory)
-        .stdout(Stdio::piped())
-        .spawn()
-        .context("rgコマンドの実行に失敗")?;
-
-    let stdout = command.stdout.context("cant read from rg process")?;
-    let reader = BufReader::new(stdout);
-    Ok(reader
-        .lines()
-        .map(|line| line.map(|s| s.trim().to_string())))
+/// Expands `~` in `path`. Non-UTF-8 paths are returned as they are.
+fn expand_home(path: PathBuf) -> io::Result<PathBuf> {
+    match path.to_str() {
+        Some(s) => expanduser(s),
+        None => Ok(path),
+    }
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-#[command(about = "Get Tags from vault")]
-struct Args {
-    /// Path to the Obsidian vault
-    #[arg(short, long)]
-    path: Option<String>,
+/// Whether the output is a plain list of tags, which can be printed as the
+/// tags are found; every other report needs all of them first.
+fn streamable(args: &Args) -> bool {
+    args.format == OutputFormat::Plain
+        && matches!(args.command, None | Some(Command::List))
+        && matches!(args.sort, None | Some(SortMode::Unsorted))
+        && args.min_count <= 1
+        && args.max_count.is_none()
+        && args.top.is_none()
+        && !(args.count
+            || args.tree
+            || args.files
+            || args.per_file
+            || args.lint
+            || args.aliases
+            || args.singleton_tags
+            || args.files_for_tag.is_some()
+            || args.group_by_vault
+            || args.expand_parents
+            || args.leaf_only
+            || args.case_insensitive
+            || args.reverse
+            || args.tag_prefix_strip)
+}
 
-    #[arg(short, long, value_name = "in_content")]
-    rg: bool,
+/// The report for `files`, from the vault at `vault_path`.
+fn build_report(
+    args: &Args,
+    vault_path: &Path,
+    files: &[PathBuf],
+    options: ScanOptions,
+    sort: SortMode,
+) -> anyhow::Result<TagReport> {
+    let mut report = if let Some(Command::Notes { tags, all, exact }) = &args.command {
+        let tag_files = gather_folded_tag_files(args, vault_path, files, options)?;
+        TagReport::Paths(notes_tagged(
+            &tag_files,
+            tags,
+            *all,
+            *exact,
+            args.case_insensitive,
+        ))
+    } else if args.lint {
+        let tag_files = gather_tag_files(args, vault_path, files, options)?;
+        TagReport::Lint(
+            lint_tags(&tag_files)
+                .into_iter()
+                .map(|(tag, error)| TagLint {
+                    tag: tag.to_string(),
+                    error: error.to_string(),
+                    files: tag_files[tag].iter().cloned().collect(),
+                })
+                .collect(),
+        )
+    } else if args.singleton_tags {
+        let tag_files = gather_folded_tag_files(args, vault_path, files, options)?;
+        let mut notes: HashMap<String, PathBuf> = tag_files
+            .into_iter()
+            .filter(|(_, files)| files.len() == 1)
+            .filter_map(|(tag, files)| Some((tag, files.into_iter().next()?)))
+            .collect();
+        let counts = notes.keys().map(|tag| (tag.clone(), 1)).collect();
+        let mut singletons: Vec<TagFile> = sort_tags(counts, sort)
+            .into_iter()
+            .filter_map(|(tag, _)| {
+                let file = notes.remove(&tag)?;
+                Some(TagFile { tag, file })
+            })
+            .collect();
+        if args.reverse {
+            singletons.reverse();
+        }
+        TagReport::Singletons(singletons)
+    } else if let Some(pattern) = &args.files_for_tag {
+        let pattern = glob::Pattern::new(pattern)?;
+        let match_options = glob::MatchOptions {
+            case_sensitive: !args.case_insensitive,
+            ..Default::default()
+        };
+        // inline tags always count, read by rg with --rg
+        let options = ScanOptions {
+            inline: options.inline || !args.rg,
+            ..options
+        };
+        let paths: BTreeSet<PathBuf> = gather_folded_tag_files(args, vault_path, files, options)?
+            .into_iter()
+            .filter(|(tag, _)| pattern.matches_with(tag, match_options))
+            .flat_map(|(_, files)| files)
+            .collect();
+        TagReport::Paths(paths.into_iter().collect())
+    } else if args.per_file {
+        let mut file_tags = collect_file_tags(files, options);
+
+        if args.rg {
+            let mut inline: HashMap<PathBuf, Vec<String>> = HashMap::new();
+            for (path, tag) in collect_inline_tag_files(files, options)? {
+                if !options.keeps(&tag) {
+                    continue;
+                }
+                inline
+                    .entry(relative_path(vault_path, &path))
+                    .or_default()
+                    .push(remove_hash(&tag).to_string());
+            }
+            for (path, tags) in &mut file_tags {
+                if let Some(found) = inline.remove(&relative_path(vault_path, path)) {
+                    for tag in found {
+                        if !tags.contains(&tag) {
+                            tags.push(tag);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut file_tags: Vec<FileTags> = file_tags
+            .into_iter()
+            .filter(|(_, tags)| args.include_empty || !tags.is_empty())
+            .map(|(path, tags)| FileTags {
+                file: relative_path(vault_path, &path),
+                tags,
+            })
+            .collect();
+        file_tags.sort_unstable_by(|a, b| a.file.cmp(&b.file));
+        TagReport::PerFile(file_tags)
+    } else if args.count || args.tree {
+        let mut tag_files = gather_folded_tag_files(args, vault_path, files, options)?;
+        let mut sorted = sort_tags(counts_within(args, &tag_files), sort);
+        if args.reverse {
+            sorted.reverse();
+        }
+        if let Some(top) = args.top {
+            sorted.truncate(top);
+        }
+        // YAML nests the counts along `/` too, so it gets the tree's
+        if args.tree || args.format == OutputFormat::Yaml {
+            let kept: TagFiles = sorted
+                .into_iter()
+                .filter_map(|(tag, _)| tag_files.remove_entry(&tag))
+                .collect();
+            TagReport::Tree {
+                tree: TagTree::from_tag_files(&kept),
+                show_counts: args.count,
+                sort,
+                reverse: args.reverse,
+            }
+        } else {
+            TagReport::Counts(
+                sorted
+                    .into_iter()
+                    .map(|(tag, count)| TagCount { tag, count })
+                    .collect(),
+            )
+        }
+    } else if args.files {
+        TagReport::files(gather_tag_files(args, vault_path, files, options)?)
+    } else if matches!(sort, SortMode::Freq | SortMode::Length)
+        || args.min_count > 1
+        || args.max_count.is_some()
+    {
+        let mut sorted = sort_tags(gather_counts(args, vault_path, files, options)?, sort);
+        if args.reverse {
+            sorted.reverse();
+        }
+        TagReport::Tags(sorted.into_iter().map(|(tag, _)| tag).collect())
+    } else {
+        let mut collected_tags = collect_tags(files, options)?;
+
+        if args.rg {
+            collected_tags.extend(
+                collect_inline_tags(files, options)?
+                    .into_iter()
+                    .filter(|tag| options.keeps(tag)),
+            );
+        }
+
+        let unique: HashSet<&str> = if args.expand_parents {
+            collected_tags
+                .iter()
+                .flat_map(|tag| parent_tags(remove_hash(tag)))
+                .filter(|tag| options.keeps(tag))
+                .collect()
+        } else {
+            collected_tags.iter().map(|tag| remove_hash(tag)).collect()
+        };
+        let unique = if args.leaf_only {
+            let ancestors = ancestor_tags(unique.iter().copied());
+            unique.difference(&ancestors).copied().collect()
+        } else {
+            unique
+        };
+        let mut tags: Vec<String> = unique.into_iter().map(String::from).collect();
+        // HashSet order changes between runs; folding needs it fixed too
+        if sort == SortMode::Alpha || args.case_insensitive {
+            tags.sort_unstable();
+        }
+        if args.case_insensitive {
+            tags = fold_case(tags);
+        }
+        if args.lowercase {
+            tags = tags.iter().map(|tag| tag.to_lowercase()).collect();
+            if sort == SortMode::Alpha {
+                tags.sort_unstable();
+            }
+        }
+        if sort == SortMode::AlphaCi {
+            tags.sort_unstable_by(|a, b| cmp_ignore_case(a, b));
+        }
+        if args.reverse {
+            tags.reverse();
+        }
+        if args.aliases {
+            let mut aliases: Vec<String> = collect_aliases(files).into_iter().collect();
+            aliases.sort_unstable();
+            TagReport::TagsAndAliases { tags, aliases }
+        } else {
+            TagReport::Tags(tags)
+        }
+    };
+    if args.tag_prefix_strip {
+        report.strip_tag_prefixes(&args.prefix);
+    }
+    if args.with_hash {
+        report.add_hashes();
+    }
+    if args.absolute || args.relative_to.is_some() {
+        report.map_paths(|path| shown_path(args, vault_path, path));
+    }
+    Ok(report)
+}
+
+/// The note at `path` in `vault_path` as file listings print it: canonical
+/// with `--absolute`, or relative to `--relative-to` when it is inside.
+fn shown_path(args: &Args, vault_path: &Path, path: &Path) -> PathBuf {
+    let full = vault_path.join(path);
+    let absolute = fs::canonicalize(&full)
+        .or_else(|_| std::path::absolute(&full))
+        .unwrap_or(full);
+    let base = match &args.relative_to {
+        Some(base) if !args.absolute => base,
+        _ => return absolute,
+    };
+    match absolute.strip_prefix(base) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => {
+            warn!(
+                "{}: not under {}, printing it absolute",
+                absolute.display(),
+                base.display()
+            );
+            absolute
+        }
+    }
 }
 
-use rayon::prelude::*;
+/// A buffered writer to `path`, after creating its parent directories. The
+/// file is truncated unless `append` is set.
+fn create_output(path: &Path, append: bool) -> anyhow::Result<BufWriter<File>> {
+    if path.is_dir() {
+        return Err(anyhow!("{}: is a directory", path.display()));
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).with_context(|| parent.display().to_string())?;
+    }
+    let file = File::options()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| path.display().to_string())?;
+    Ok(BufWriter::new(file))
+}
 
-fn collect_tags(paths: &Vec<PathBuf>) -> Result<HashSet<String>> {
-    let result = paths
-        .into_par_iter()
-        .filter_map(|path| load_tags(path).ok())
-        .flatten()
-        .collect();
-    Ok(result)
+/// How many invalid tags a `--lint` report lists.
+fn lint_count(report: &TagReport) -> usize {
+    match report {
+        TagReport::Lint(lints) => lints.len(),
+        _ => 0,
+    }
+}
+
+/// The last component of `vault`, to tell the vaults apart in grouped output.
+fn vault_name(vault: &Path) -> String {
+    let name = vault
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.file_name().map(ToOwned::to_owned));
+    match name.or_else(|| vault.file_name().map(ToOwned::to_owned)) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => vault.display().to_string(),
+    }
 }
 
-fn collect_paths(root: &Path) -> Vec<PathBuf> {
-    let paths: Vec<_> = WalkDir::new(root)
+/// `vaults` without the ones given twice, possibly spelled differently.
+fn dedup_vaults(vaults: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    vaults
         .into_iter()
-        .filter_map(|entry| entry.ok().map(|e| e.path().to_path_buf()))
-        .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "md"))
-        .collect();
-    paths
+        .filter(|vault| seen.insert(vault.canonicalize().unwrap_or_else(|_| vault.clone())))
+        .collect()
 }
 
-fn main() -> anyhow::Result<()> {
-    env_logger::init();
+/// Prints the tags of the vault, then the tags added and removed as notes
+/// change, until interrupted.
+fn watch(vault_path: &Path, walk: &WalkOptions, args: &Args) -> anyhow::Result<()> {
+    // notify reports absolute paths
+    let root = vault_path.canonicalize()?;
+    let options = ScanOptions {
+        inline: args.inline || args.rg,
+        errors: None,
+        prefixes: &args.prefix,
+        cache: None,
+        legacy_props: args.legacy_props,
+        tags_keys: &args.tags_key,
+        tag_pattern: Some(&args.tag_pattern),
+        scan_drawings: args.no_skip_excalidraw,
+        include_code: args.include_code_tags,
+        progress: None,
+        rg_failed: None,
+        min_length: args.tag_length_min,
+        max_length: args.tag_length_max,
+    };
+    let mut index = TagIndex::build(&root, &collect_paths_with(&root, walk)?, options);
 
-    dotenv().ok();
+    let mut out = io::stdout().lock();
+    for event in index.initial_events() {
+        writeln!(out, "{}", event.to_line(args.watch_format))?;
+    }
+    out.flush()?;
 
-    let args = Args::parse();
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    info!("watching {}", root.display());
 
-    let vault_path = if let Some(path) = args.path {
-        path
-    } else {
-        env::var_os("OBSIDIAN_VAULT_PATH")
-            .map(|x| x.into_string().expect("failed to convert path"))
-            .ok_or(anyhow!("OBSIDIAN_VAULT_PATH not set"))?
+    for event in rx {
+        let event = match event {
+            // our own reads show up as access events
+            Ok(event) if event.kind.is_access() => continue,
+            Ok(event) => event,
+            Err(e) => {
+                warn!("{}", e);
+                continue;
+            }
+        };
+        for path in event.paths {
+            let changes = if path.is_file() {
+                if !is_collected(&root, &path, walk) {
+                    continue;
+                }
+                index.update(&path, options)
+            } else {
+                index.update_dir(&path, options, |note| is_collected(&root, note, walk))
+            };
+            for change in changes {
+                writeln!(out, "{}", change.to_line(args.watch_format))?;
+            }
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Runs `f` on `pool` when there is one, on rayon's global pool otherwise.
+fn install<T: Send>(pool: Option<&rayon::ThreadPool>, f: impl FnOnce() -> T + Send) -> T {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// Prints the tags of `old` and `new` side by side, see [`TagReport::diff`].
+fn diff(
+    old: &Path,
+    new: &Path,
+    walk: &WalkOptions,
+    args: &Args,
+    show_files: bool,
+) -> anyhow::Result<()> {
+    let rg_failed = AtomicBool::new(false);
+    let options = ScanOptions {
+        inline: args.inline,
+        errors: None,
+        prefixes: &args.prefix,
+        cache: None,
+        legacy_props: args.legacy_props,
+        tags_keys: &args.tags_key,
+        tag_pattern: Some(&args.tag_pattern),
+        scan_drawings: args.no_skip_excalidraw,
+        include_code: args.include_code_tags,
+        progress: None,
+        rg_failed: Some(&rg_failed),
+        min_length: args.tag_length_min,
+        max_length: args.tag_length_max,
+    };
+    let tag_files = |vault: &Path| {
+        let files = collect_paths_with(vault, walk)?;
+        gather_folded_tag_files(args, vault, &files, options)
     };
+    let mut report = TagReport::diff(tag_files(old)?, tag_files(new)?, show_files);
+    if args.tag_prefix_strip {
+        report.strip_tag_prefixes(&args.prefix);
+    }
+    if args.with_hash {
+        report.add_hashes();
+    }
 
-    let vault_path: PathBuf = expanduser(vault_path)?;
-    let files = collect_paths(&vault_path);
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(create_output(path, args.append)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    report.write(args.format, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
 
-    let mut collected_tags = collect_tags(&files)?;
+/// Tag to the notes (relative to the vault) using it, from front matter,
+/// plus inline tags found through rg when asked.
+fn gather_tag_files(
+    args: &Args,
+    vault_path: &Path,
+    files: &[PathBuf],
+    options: ScanOptions,
+) -> anyhow::Result<TagFiles> {
+    let mut tag_files = collect_tag_files(vault_path, files, options);
 
     if args.rg {
-        let tags = collect_obsidian_tags(vault_path.to_str().expect("utf8 error"))?;
-        tags.into_iter().for_each(|tag| match tag {
-            Ok(tag) => {
-                collected_tags.insert(tag);
+        for (path, tag) in collect_inline_tag_files(files, options)? {
+            if !options.keeps(&tag) {
+                continue;
             }
-            Err(e) => error!("error occured: {:?}", e),
-        });
+            tag_files
+                .entry(remove_hash(&tag).to_string())
+                .or_default()
+                .insert(relative_path(vault_path, &path));
+        }
+    }
+
+    // the tree draws the ancestors by itself
+    if args.expand_parents && !args.tree {
+        tag_files = expand_tag_files(tag_files);
+        tag_files.retain(|tag, _| options.keeps(tag));
+    }
+    if args.leaf_only {
+        let ancestors: HashSet<String> = ancestor_tags(tag_files.keys().map(String::as_str))
+            .into_iter()
+            .map(String::from)
+            .collect();
+        tag_files.retain(|tag, _| !ancestors.contains(tag));
     }
 
-    for tag in collected_tags {
-        println!("{}", remove_hash(&tag));
+    Ok(tag_files)
+}
+
+/// Same as [`gather_tag_files`], with case variants merged as asked.
+fn gather_folded_tag_files(
+    args: &Args,
+    vault_path: &Path,
+    files: &[PathBuf],
+    options: ScanOptions,
+) -> anyhow::Result<TagFiles> {
+    let mut tag_files = gather_tag_files(args, vault_path, files, options)?;
+    if args.case_insensitive {
+        tag_files = fold_case_tag_files(tag_files);
     }
+    if args.lowercase {
+        // folded first, so no two tags are lowercased alike
+        tag_files = tag_files
+            .into_iter()
+            .map(|(tag, files)| (tag.to_lowercase(), files))
+            .collect();
+    }
+    Ok(tag_files)
+}
 
-    Ok(())
+/// In how many notes each tag is used, within --min-count and --max-count.
+fn gather_counts(
+    args: &Args,
+    vault_path: &Path,
+    files: &[PathBuf],
+    options: ScanOptions,
+) -> anyhow::Result<Vec<(String, usize)>> {
+    Ok(counts_within(
+        args,
+        &gather_folded_tag_files(args, vault_path, files, options)?,
+    ))
 }
 
-fn remove_hash(s: &str) -> &str {
-    s.trim_start_matches('#')
+/// In how many of `tag_files` each tag is used, within --min-count and
+/// --max-count.
+fn counts_within(args: &Args, tag_files: &TagFiles) -> Vec<(String, usize)> {
+    tag_files
+        .iter()
+        .map(|(tag, files)| (tag.clone(), files.len()))
+        .filter(|(_, count)| {
+            *count >= args.min_count && args.max_count.is_none_or(|max| *count <= max)
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -194,7 +1412,6 @@ mod tests {
 
     #[test]
     fn verify_cli() {
-        use clap::CommandFactory;
         Args::command().debug_assert();
     }
 }
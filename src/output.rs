@@ -0,0 +1,882 @@
+//! Rendering of the collected tags in the supported output formats.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter};
+
+use crate::tree::TagTree;
+use crate::{strip_tag_prefix, SortMode, YamlError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One tag per line
+    Plain,
+    /// Pretty-printed JSON, sorted
+    Json,
+    /// Same as `json`, on a single line
+    JsonCompact,
+    /// Comma-separated values with a header row, sorted
+    Csv,
+    /// YAML: a `tags:` list ready for front matter, or with `--count` a
+    /// mapping nested along `/` like the Obsidian tag pane, where a tag with
+    /// subtags has its count, theirs included, under `_count`
+    Yaml,
+}
+
+/// Tag (without `#`) to the vault-relative paths of the files carrying it.
+pub type TagFiles = BTreeMap<String, BTreeSet<PathBuf>>;
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagWithFiles {
+    pub tag: String,
+    #[serde(serialize_with = "slash_paths")]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FileTags {
+    #[serde(serialize_with = "slash_path")]
+    pub file: PathBuf,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagFile {
+    pub tag: String,
+    #[serde(serialize_with = "slash_path")]
+    pub file: PathBuf,
+}
+
+/// A path with `/` separators on every platform, for scripts reading the
+/// structured output.
+fn slash_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    let path = path.to_string_lossy();
+    match std::path::MAIN_SEPARATOR {
+        '/' => serializer.serialize_str(&path),
+        separator => serializer.serialize_str(&path.replace(separator, "/")),
+    }
+}
+
+fn slash_paths<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| SlashPath(path)))
+}
+
+struct SlashPath<'a>(&'a Path);
+
+impl Serialize for SlashPath<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        slash_path(self.0, serializer)
+    }
+}
+
+/// On which side of a diff a tag is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagChange {
+    /// Only in the first vault
+    Removed,
+    /// Only in the second vault
+    Added,
+    /// In both
+    Common,
+}
+
+impl TagChange {
+    /// How plain output marks the tag, like a unified diff does lines.
+    pub fn marker(self) -> char {
+        match self {
+            TagChange::Removed => '-',
+            TagChange::Added => '+',
+            TagChange::Common => ' ',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagDiff {
+    pub tag: String,
+    pub change: TagChange,
+    /// The notes carrying a removed or added tag, when asked for
+    #[serde(skip_serializing_if = "Vec::is_empty", serialize_with = "slash_paths")]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagLint {
+    pub tag: String,
+    /// Why the tag is invalid
+    pub error: String,
+    #[serde(serialize_with = "slash_paths")]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// The result of a run, in the order it is printed.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum TagReport {
+    /// A JSON array of strings
+    Tags(Vec<String>),
+    /// A JSON array of `{ "tag": ..., "count": ... }` objects
+    Counts(Vec<TagCount>),
+    /// A JSON array of `{ "tag": ..., "files": [...] }` objects
+    Files(Vec<TagWithFiles>),
+    /// A JSON object `{ "tags": [...], "aliases": [...] }`
+    TagsAndAliases {
+        tags: Vec<String>,
+        aliases: Vec<String>,
+    },
+    /// A JSON array of `{ "file": ..., "tags": [...] }` objects
+    PerFile(Vec<FileTags>),
+    /// A JSON array of file paths
+    Paths(#[serde(serialize_with = "slash_paths")] Vec<PathBuf>),
+    /// A JSON array of `{ "tag": ..., "file": ... }` objects, for tags used
+    /// by a single note
+    Singletons(Vec<TagFile>),
+    /// A JSON array of `{ "tag": ..., "error": ..., "files": [...] }` objects
+    Lint(Vec<TagLint>),
+    /// A JSON array of `{ "tag": ..., "change": ..., "files": [...] }` objects
+    Diff(Vec<TagDiff>),
+    /// Nested `{ "count": ..., "children": { ... } }` objects
+    Tree {
+        #[serde(flatten)]
+        tree: TagTree,
+        #[serde(skip)]
+        show_counts: bool,
+        /// Order of the siblings in plain and CSV output
+        #[serde(skip)]
+        sort: SortMode,
+        #[serde(skip)]
+        reverse: bool,
+    },
+}
+
+impl TagReport {
+    pub fn files(tag_files: TagFiles) -> Self {
+        TagReport::Files(
+            tag_files
+                .into_iter()
+                .map(|(tag, files)| TagWithFiles {
+                    tag,
+                    files: files.into_iter().collect(),
+                })
+                .collect(),
+        )
+    }
+
+    /// The tags of `old` and `new` side by side, sorted. With `show_files`,
+    /// the notes carrying each tag found on one side only are kept.
+    pub fn diff(old: TagFiles, new: TagFiles, show_files: bool) -> Self {
+        let mut new = new;
+        let mut diff = Vec::new();
+        for (tag, files) in old {
+            let change = match new.remove(&tag) {
+                Some(_) => TagChange::Common,
+                None => TagChange::Removed,
+            };
+            diff.push((tag, change, files));
+        }
+        diff.extend(
+            new.into_iter()
+                .map(|(tag, files)| (tag, TagChange::Added, files)),
+        );
+        diff.sort_by(|a, b| a.0.cmp(&b.0));
+        TagReport::Diff(
+            diff.into_iter()
+                .map(|(tag, change, files)| TagDiff {
+                    tag,
+                    change,
+                    files: if show_files && change != TagChange::Common {
+                        files.into_iter().collect()
+                    } else {
+                        Vec::new()
+                    },
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether there is nothing to report.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            TagReport::Tags(tags) => tags.is_empty(),
+            TagReport::Counts(counts) => counts.is_empty(),
+            TagReport::Files(files) => files.is_empty(),
+            TagReport::TagsAndAliases { tags, aliases } => tags.is_empty() && aliases.is_empty(),
+            TagReport::PerFile(files) => files.is_empty(),
+            TagReport::Paths(paths) => paths.is_empty(),
+            TagReport::Singletons(tags) => tags.is_empty(),
+            TagReport::Lint(lints) => lints.is_empty(),
+            TagReport::Diff(diff) => diff.is_empty(),
+            TagReport::Tree { tree, .. } => tree.children.is_empty(),
+        }
+    }
+
+    /// Drops the longest of `prefixes` from every tag, see
+    /// [`strip_tag_prefix`]. Plain tag lists are deduplicated afterwards, but
+    /// trees are left alone since their nesting is the prefix.
+    pub fn strip_tag_prefixes(&mut self, prefixes: &[String]) {
+        let strip = |tag: &mut String| *tag = strip_tag_prefix(tag, prefixes).to_string();
+        match self {
+            TagReport::Tags(tags) | TagReport::TagsAndAliases { tags, .. } => {
+                tags.iter_mut().for_each(strip);
+                let mut seen = BTreeSet::new();
+                tags.retain(|tag| seen.insert(tag.clone()));
+            }
+            TagReport::Counts(counts) => counts.iter_mut().for_each(|c| strip(&mut c.tag)),
+            TagReport::Files(files) => files.iter_mut().for_each(|f| strip(&mut f.tag)),
+            TagReport::PerFile(files) => files
+                .iter_mut()
+                .for_each(|f| f.tags.iter_mut().for_each(strip)),
+            TagReport::Lint(lints) => lints.iter_mut().for_each(|l| strip(&mut l.tag)),
+            TagReport::Singletons(tags) => tags.iter_mut().for_each(|t| strip(&mut t.tag)),
+            TagReport::Diff(diff) => diff.iter_mut().for_each(|d| strip(&mut d.tag)),
+            TagReport::Paths(_) | TagReport::Tree { .. } => {}
+        }
+    }
+
+    /// Replaces every file path with `f` of it. Diffs are left alone, their
+    /// paths being in two vaults.
+    pub fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        let map = |path: &mut PathBuf| *path = f(path);
+        match self {
+            TagReport::Files(files) => files
+                .iter_mut()
+                .for_each(|tag| tag.files.iter_mut().for_each(map)),
+            TagReport::PerFile(files) => files.iter_mut().for_each(|file| map(&mut file.file)),
+            TagReport::Paths(paths) => paths.iter_mut().for_each(map),
+            TagReport::Singletons(tags) => tags.iter_mut().for_each(|t| map(&mut t.file)),
+            TagReport::Lint(lints) => lints
+                .iter_mut()
+                .for_each(|l| l.files.iter_mut().for_each(map)),
+            TagReport::Tags(_)
+            | TagReport::Counts(_)
+            | TagReport::TagsAndAliases { .. }
+            | TagReport::Diff(_)
+            | TagReport::Tree { .. } => {}
+        }
+    }
+
+    /// Writes every tag with a leading `#`, as in Obsidian searches. Trees are
+    /// left alone, their nodes being segments rather than tags.
+    pub fn add_hashes(&mut self) {
+        let add = |tag: &mut String| tag.insert(0, '#');
+        match self {
+            TagReport::Tags(tags) | TagReport::TagsAndAliases { tags, .. } => {
+                tags.iter_mut().for_each(add)
+            }
+            TagReport::Counts(counts) => counts.iter_mut().for_each(|c| add(&mut c.tag)),
+            TagReport::Files(files) => files.iter_mut().for_each(|f| add(&mut f.tag)),
+            TagReport::PerFile(files) => files
+                .iter_mut()
+                .for_each(|f| f.tags.iter_mut().for_each(add)),
+            TagReport::Lint(lints) => lints.iter_mut().for_each(|l| add(&mut l.tag)),
+            TagReport::Singletons(tags) => tags.iter_mut().for_each(|t| add(&mut t.tag)),
+            TagReport::Diff(diff) => diff.iter_mut().for_each(|d| add(&mut d.tag)),
+            TagReport::Paths(_) | TagReport::Tree { .. } => {}
+        }
+    }
+
+    pub fn write(&self, format: OutputFormat, out: &mut impl Write) -> Result<()> {
+        match format {
+            OutputFormat::Plain => self.write_plain(out)?,
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)?;
+            }
+            OutputFormat::JsonCompact => {
+                serde_json::to_writer(&mut *out, self)?;
+                writeln!(out)?;
+            }
+            OutputFormat::Csv => self.write_csv(out)?,
+            OutputFormat::Yaml => write_yaml(&self.to_yaml(), out)?,
+        }
+        Ok(())
+    }
+
+    /// The report as YAML: counts and trees as nested mappings, anything else
+    /// as its JSON shape.
+    pub fn to_yaml(&self) -> Yaml {
+        match self {
+            TagReport::Counts(counts) => tree_yaml(&TagTree::from_counts(
+                counts
+                    .iter()
+                    .map(|TagCount { tag, count }| (tag.as_str(), *count)),
+            )),
+            TagReport::Tree { tree, .. } => tree_yaml(tree),
+            TagReport::Tags(tags) => {
+                let mut tags = tags.clone();
+                tags.sort();
+                let mut hash = Hash::new();
+                hash.insert(
+                    Yaml::String("tags".into()),
+                    Yaml::Array(tags.into_iter().map(Yaml::String).collect()),
+                );
+                Yaml::Hash(hash)
+            }
+            _ => json_to_yaml(serde_json::to_value(self).expect("reports serialize")),
+        }
+    }
+
+    /// Plain output with `separator` after each entry instead of a newline,
+    /// e.g. `\0` for `xargs -0`. Only lists of tags or paths can be written so.
+    pub fn write_separated(&self, separator: char, out: &mut impl Write) -> Result<()> {
+        match self {
+            TagReport::Tags(tags) => write_tags(tags, separator, out)?,
+            TagReport::Paths(paths) => {
+                let paths: Vec<_> = paths.iter().map(|path| path.display()).collect();
+                write_tags(&paths, separator, out)?
+            }
+            _ => anyhow::bail!("a custom delimiter only applies to plain lists of tags or files"),
+        }
+        Ok(())
+    }
+
+    fn write_plain(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            TagReport::Tags(tags) => {
+                for tag in tags {
+                    writeln!(out, "{}", tag)?;
+                }
+            }
+            TagReport::TagsAndAliases { tags, aliases } => {
+                for tag in tags {
+                    writeln!(out, "{}", tag)?;
+                }
+                for alias in aliases {
+                    writeln!(out, "alias: {}", alias)?;
+                }
+            }
+            TagReport::Counts(counts) => {
+                for TagCount { tag, count } in counts {
+                    writeln!(out, "{}\t{}", count, tag)?;
+                }
+            }
+            TagReport::Files(tag_files) => {
+                for TagWithFiles { tag, files } in tag_files {
+                    let files: Vec<_> = files.iter().map(|file| file.to_string_lossy()).collect();
+                    writeln!(out, "{}: {}", tag, files.join(", "))?;
+                }
+            }
+            TagReport::PerFile(file_tags) => {
+                for FileTags { file, tags } in file_tags {
+                    writeln!(out, "{}: {}", file.display(), tags.join(", "))?;
+                }
+            }
+            TagReport::Paths(paths) => {
+                for path in paths {
+                    writeln!(out, "{}", path.display())?;
+                }
+            }
+            TagReport::Lint(lints) => {
+                for TagLint { tag, error, files } in lints {
+                    let files: Vec<_> = files.iter().map(|file| file.to_string_lossy()).collect();
+                    writeln!(out, "{}: {} ({})", tag, error, files.join(", "))?;
+                }
+            }
+            TagReport::Singletons(tags) => {
+                for TagFile { tag, file } in tags {
+                    writeln!(out, "{}\t{}", tag, file.display())?;
+                }
+            }
+            TagReport::Diff(diff) => {
+                for TagDiff { tag, change, files } in diff {
+                    if files.is_empty() {
+                        writeln!(out, "{}{}", change.marker(), tag)?;
+                    } else {
+                        let files: Vec<_> =
+                            files.iter().map(|file| file.to_string_lossy()).collect();
+                        writeln!(out, "{}{}: {}", change.marker(), tag, files.join(", "))?;
+                    }
+                }
+            }
+            TagReport::Tree {
+                tree,
+                show_counts,
+                sort,
+                reverse,
+            } => tree.write(out, *show_counts, *sort, *reverse)?,
+        }
+        Ok(())
+    }
+
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            TagReport::Tags(tags) => {
+                writeln!(out, "tag")?;
+                for tag in tags {
+                    writeln!(out, "{}", csv_field(tag))?;
+                }
+            }
+            TagReport::TagsAndAliases { tags, aliases } => {
+                writeln!(out, "kind,name")?;
+                for tag in tags {
+                    writeln!(out, "tag,{}", csv_field(tag))?;
+                }
+                for alias in aliases {
+                    writeln!(out, "alias,{}", csv_field(alias))?;
+                }
+            }
+            TagReport::Counts(counts) => {
+                writeln!(out, "count,tag")?;
+                for TagCount { tag, count } in counts {
+                    writeln!(out, "{},{}", count, csv_field(tag))?;
+                }
+            }
+            TagReport::Files(tag_files) => {
+                writeln!(out, "tag,file")?;
+                for TagWithFiles { tag, files } in tag_files {
+                    for file in files {
+                        let file = file.to_string_lossy();
+                        writeln!(out, "{},{}", csv_field(tag), csv_field(&file))?;
+                    }
+                }
+            }
+            TagReport::PerFile(file_tags) => {
+                writeln!(out, "file,tag")?;
+                for FileTags { file, tags } in file_tags {
+                    let file = file.to_string_lossy();
+                    if tags.is_empty() {
+                        writeln!(out, "{},", csv_field(&file))?;
+                    }
+                    for tag in tags {
+                        writeln!(out, "{},{}", csv_field(&file), csv_field(tag))?;
+                    }
+                }
+            }
+            TagReport::Lint(lints) => {
+                writeln!(out, "tag,error,file")?;
+                for TagLint { tag, error, files } in lints {
+                    for file in files {
+                        let file = file.to_string_lossy();
+                        writeln!(
+                            out,
+                            "{},{},{}",
+                            csv_field(tag),
+                            csv_field(error),
+                            csv_field(&file)
+                        )?;
+                    }
+                }
+            }
+            TagReport::Diff(diff) => {
+                writeln!(out, "change,tag,file")?;
+                for TagDiff { tag, change, files } in diff {
+                    let change = change.marker();
+                    if files.is_empty() {
+                        writeln!(out, "{},{},", change, csv_field(tag))?;
+                    }
+                    for file in files {
+                        let file = file.to_string_lossy();
+                        writeln!(out, "{},{},{}", change, csv_field(tag), csv_field(&file))?;
+                    }
+                }
+            }
+            TagReport::Singletons(tags) => {
+                writeln!(out, "tag,file")?;
+                for TagFile { tag, file } in tags {
+                    let file = file.to_string_lossy();
+                    writeln!(out, "{},{}", csv_field(tag), csv_field(&file))?;
+                }
+            }
+            TagReport::Paths(paths) => {
+                writeln!(out, "file")?;
+                for path in paths {
+                    writeln!(out, "{}", csv_field(&path.to_string_lossy()))?;
+                }
+            }
+            TagReport::Tree {
+                tree,
+                sort,
+                reverse,
+                ..
+            } => {
+                writeln!(out, "count,tag")?;
+                write_tree_csv(tree, "", *sort, *reverse, out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One report per vault, named by the first element of each pair. Plain lines
+/// are prefixed with `name: `, CSV rows get a leading `vault` column and JSON
+/// nests each report under its vault name.
+pub fn write_grouped(
+    reports: &[(String, TagReport)],
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<()> {
+    match format {
+        OutputFormat::Plain | OutputFormat::Csv => {
+            for (i, (name, report)) in reports.iter().enumerate() {
+                let mut rendered = Vec::new();
+                report.write(format, &mut rendered)?;
+                let rendered = String::from_utf8_lossy(&rendered);
+                let mut lines = rendered.lines();
+                if format == OutputFormat::Csv {
+                    // a single header row, for the whole output
+                    let header = lines.next().unwrap_or_default();
+                    if i == 0 {
+                        writeln!(out, "vault,{}", header)?;
+                    }
+                    for line in lines {
+                        writeln!(out, "{},{}", csv_field(name), line)?;
+                    }
+                } else {
+                    for line in lines {
+                        writeln!(out, "{}: {}", name, line)?;
+                    }
+                }
+            }
+        }
+        OutputFormat::Yaml => {
+            let grouped = reports
+                .iter()
+                .map(|(name, report)| (Yaml::String(name.clone()), report.to_yaml()))
+                .collect();
+            write_yaml(&Yaml::Hash(grouped), out)?;
+        }
+        OutputFormat::Json | OutputFormat::JsonCompact => {
+            let grouped = Grouped(reports);
+            if format == OutputFormat::Json {
+                serde_json::to_writer_pretty(&mut *out, &grouped)?;
+            } else {
+                serde_json::to_writer(&mut *out, &grouped)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reserved key holding the count of a tag that has subtags.
+pub const YAML_COUNT_KEY: &str = "_count";
+
+/// The children of `tree` as a mapping: a tag without subtags maps to its
+/// count, one with subtags to a mapping of them plus its count, theirs
+/// included, under [`YAML_COUNT_KEY`].
+fn tree_yaml(tree: &TagTree) -> Yaml {
+    Yaml::Hash(
+        tree.children
+            .iter()
+            .map(|(name, child)| {
+                let value = if child.children.is_empty() {
+                    Yaml::Integer(child.count as i64)
+                } else {
+                    let mut node = Hash::new();
+                    node.insert(
+                        Yaml::String(YAML_COUNT_KEY.to_string()),
+                        Yaml::Integer(child.count as i64),
+                    );
+                    if let Yaml::Hash(children) = tree_yaml(child) {
+                        node.extend(children);
+                    }
+                    Yaml::Hash(node)
+                };
+                (Yaml::String(name.clone()), value)
+            })
+            .collect(),
+    )
+}
+
+fn json_to_yaml(value: serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(b) => Yaml::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        serde_json::Value::String(s) => Yaml::String(s),
+        serde_json::Value::Array(values) => {
+            Yaml::Array(values.into_iter().map(json_to_yaml).collect())
+        }
+        serde_json::Value::Object(map) => Yaml::Hash(
+            map.into_iter()
+                .map(|(key, value)| (Yaml::String(key), json_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn write_yaml(yaml: &Yaml, out: &mut impl Write) -> Result<()> {
+    let mut text = String::new();
+    YamlEmitter::new(&mut text).dump(yaml)?;
+    writeln!(out, "{}", text)?;
+    Ok(())
+}
+
+/// Serializes as a JSON object, keeping the order of the vaults.
+struct Grouped<'a>(&'a [(String, TagReport)]);
+
+impl Serialize for Grouped<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, report)| (name, report)))
+    }
+}
+
+/// A table of the files whose front matter could not be read, one row per
+/// file with the error variant and its message.
+pub fn write_error_report(
+    errors: &[(PathBuf, YamlError)],
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let files: Vec<_> = errors
+        .iter()
+        .map(|(path, _)| path.display().to_string())
+        .collect();
+    let file_width = files
+        .iter()
+        .map(|f| f.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let kind_width = errors
+        .iter()
+        .map(|(_, e)| e.kind().len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    writeln!(
+        out,
+        "{:file_width$}  {:kind_width$}  message",
+        "file", "error"
+    )?;
+    for (file, (_, error)) in files.iter().zip(errors) {
+        writeln!(
+            out,
+            "{:file_width$}  {:kind_width$}  {}",
+            file,
+            error.kind(),
+            error
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes each of `tags` followed by `separator`.
+pub fn write_tags(
+    tags: &[impl Display],
+    separator: char,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    for tag in tags {
+        write!(out, "{}{}", tag, separator)?;
+    }
+    Ok(())
+}
+
+/// One row per node, with its full `/`-joined tag.
+fn write_tree_csv(
+    tree: &TagTree,
+    parent: &str,
+    sort: SortMode,
+    reverse: bool,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    for (name, child) in tree.sorted_children(sort, reverse) {
+        let tag = if parent.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", parent, name)
+        };
+        writeln!(out, "{},{}", child.count, csv_field(&tag))?;
+        write_tree_csv(child, &tag, sort, reverse, out)?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field when it contains a delimiter, a quote or a line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(report: &TagReport, format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        report.write(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("project/alpha"), "project/alpha");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn error_report_is_aligned() {
+        let errors = vec![
+            (PathBuf::from("a.md"), YamlError::InvalidTagsType),
+            (PathBuf::from("notes/b.md"), YamlError::InvalidAliasesType),
+        ];
+        let mut out = Vec::new();
+        write_error_report(&errors, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines[0], "file        error               message");
+        assert!(lines[1].starts_with("a.md        InvalidTagsType     Expected 'tags'"));
+        assert!(lines[2].starts_with("notes/b.md  InvalidAliasesType  Expected 'aliases'"));
+    }
+
+    #[test]
+    fn tags_with_custom_separator() {
+        let tags = ["a b", "c"];
+        let mut out = Vec::new();
+        write_tags(&tags, '\0', &mut out).unwrap();
+        assert_eq!(out, b"a b\0c\0");
+
+        let mut out = Vec::new();
+        write_tags(&tags, ',', &mut out).unwrap();
+        assert_eq!(out, b"a b,c,");
+    }
+
+    #[test]
+    fn files_one_line_per_tag() {
+        let mut tag_files = TagFiles::new();
+        tag_files
+            .entry("foo".to_string())
+            .or_default()
+            .extend([PathBuf::from("note2.md"), PathBuf::from("dir/note1.md")]);
+        tag_files
+            .entry("bar".to_string())
+            .or_default()
+            .insert(PathBuf::from("note2.md"));
+        assert_eq!(
+            render(&TagReport::files(tag_files), OutputFormat::Plain),
+            "bar: note2.md\nfoo: dir/note1.md, note2.md\n"
+        );
+    }
+
+    #[test]
+    fn diff_marks_each_side() {
+        let tag_files = |tags: &[(&str, &str)]| {
+            let mut tag_files = TagFiles::new();
+            for (tag, file) in tags {
+                tag_files
+                    .entry(tag.to_string())
+                    .or_default()
+                    .insert(PathBuf::from(file));
+            }
+            tag_files
+        };
+        let old = tag_files(&[("gone", "a.md"), ("kept", "a.md")]);
+        let new = tag_files(&[("kept", "b.md"), ("new", "b.md")]);
+
+        assert_eq!(
+            render(
+                &TagReport::diff(old.clone(), new.clone(), false),
+                OutputFormat::Plain
+            ),
+            "-gone\n kept\n+new\n"
+        );
+        assert_eq!(
+            render(&TagReport::diff(old, new, true), OutputFormat::Plain),
+            "-gone: a.md\n kept\n+new: b.md\n"
+        );
+    }
+
+    #[test]
+    fn grouped_by_vault() {
+        let reports = vec![
+            (
+                "work".to_string(),
+                TagReport::Tags(vec!["a".to_string(), "b".to_string()]),
+            ),
+            ("home".to_string(), TagReport::Tags(vec!["a".to_string()])),
+        ];
+        let render = |format| {
+            let mut out = Vec::new();
+            write_grouped(&reports, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(render(OutputFormat::Plain), "work: a\nwork: b\nhome: a\n");
+        assert_eq!(
+            render(OutputFormat::Csv),
+            "vault,tag\nwork,a\nwork,b\nhome,a\n"
+        );
+        assert_eq!(
+            render(OutputFormat::JsonCompact),
+            "{\"work\":[\"a\",\"b\"],\"home\":[\"a\"]}\n"
+        );
+    }
+
+    #[test]
+    fn yaml_counts_round_trip() {
+        let report = TagReport::Counts(vec![
+            TagCount {
+                tag: "project".to_string(),
+                count: 1,
+            },
+            TagCount {
+                tag: "project/alpha".to_string(),
+                count: 3,
+            },
+            TagCount {
+                tag: "solo".to_string(),
+                count: 2,
+            },
+        ]);
+        let text = render(&report, OutputFormat::Yaml);
+        let docs = yaml_rust::YamlLoader::load_from_str(&text).unwrap();
+        let yaml = &docs[0];
+
+        assert_eq!(yaml["solo"].as_i64(), Some(2));
+        assert_eq!(yaml["project"]["alpha"].as_i64(), Some(3));
+        assert_eq!(yaml["project"][YAML_COUNT_KEY].as_i64(), Some(4));
+    }
+
+    #[test]
+    fn yaml_tags_are_a_sorted_list() {
+        let report = TagReport::Tags(vec!["zeta".to_string(), "alpha".to_string()]);
+        let text = render(&report, OutputFormat::Yaml);
+
+        assert!(text.contains("tags:\n  - alpha\n  - zeta"), "{}", text);
+    }
+
+    #[test]
+    fn empty_report_is_valid_json() {
+        assert_eq!(
+            render(&TagReport::Tags(Vec::new()), OutputFormat::Json),
+            "[]\n"
+        );
+    }
+
+    #[test]
+    fn counts_as_json_objects() {
+        let report = TagReport::Counts(vec![TagCount {
+            tag: "foo".to_string(),
+            count: 2,
+        }]);
+        assert_eq!(
+            render(&report, OutputFormat::JsonCompact),
+            "[{\"tag\":\"foo\",\"count\":2}]\n"
+        );
+    }
+}
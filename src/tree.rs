@@ -0,0 +1,190 @@
+//! Nested tags (`project/work/q1`) arranged as a tree.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::output::TagFiles;
+use crate::{expand_tag_files, remove_hash, sort_tags, SortMode};
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TagTree {
+    /// Occurrences of this tag and all of its subtags, or with
+    /// [`TagTree::from_tag_files`] the notes having any of them
+    pub count: usize,
+    pub children: BTreeMap<String, TagTree>,
+}
+
+impl TagTree {
+    /// Builds a tree from `(tag, count)` pairs.
+    pub fn from_counts<'a>(counts: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let mut tree = TagTree::default();
+        for (tag, count) in counts {
+            tree.insert(tag, count);
+        }
+        tree
+    }
+
+    /// Builds a tree from the notes of each tag, a note having several tags of
+    /// a node counting once for it.
+    pub fn from_tag_files(tag_files: &TagFiles) -> Self {
+        let notes: HashSet<_> = tag_files.values().flatten().collect();
+        let mut tree = TagTree {
+            count: notes.len(),
+            ..Default::default()
+        };
+        // every ancestor of a tag gets its own entry, with its subtags' notes
+        for (tag, files) in expand_tag_files(tag_files.clone()) {
+            let mut node = &mut tree;
+            for segment in remove_hash(&tag).split('/') {
+                node = node.children.entry(segment.to_string()).or_default();
+            }
+            node.count = files.len();
+        }
+        tree
+    }
+
+    /// Adds `count` occurrences of `tag` to the node of every `/` segment.
+    pub fn insert(&mut self, tag: &str, count: usize) {
+        self.count += count;
+        let mut node = self;
+        for segment in remove_hash(tag).split('/') {
+            node = node.children.entry(segment.to_string()).or_default();
+            node.count += count;
+        }
+    }
+
+    /// The subtags, ordered by [`sort_tags`] on their segment and count, then
+    /// reversed if `reverse`.
+    pub fn sorted_children(&self, sort: SortMode, reverse: bool) -> Vec<(&str, &TagTree)> {
+        let counts = self
+            .children
+            .iter()
+            .map(|(name, child)| (name.clone(), child.count))
+            .collect();
+        let mut sorted: Vec<_> = sort_tags(counts, sort)
+            .into_iter()
+            .filter_map(|(name, _)| self.children.get_key_value(&name))
+            .map(|(name, child)| (name.as_str(), child))
+            .collect();
+        if reverse {
+            sorted.reverse();
+        }
+        sorted
+    }
+
+    /// Draws the tree, one root tag per top-level line and subtags below it,
+    /// siblings ordered as in [`TagTree::sorted_children`].
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        show_counts: bool,
+        sort: SortMode,
+        reverse: bool,
+    ) -> std::io::Result<()> {
+        for (name, child) in self.sorted_children(sort, reverse) {
+            writeln!(out, "{}", child.label(name, show_counts))?;
+            child.write_children(out, "", show_counts, sort, reverse)?;
+        }
+        Ok(())
+    }
+
+    fn write_children(
+        &self,
+        out: &mut impl Write,
+        prefix: &str,
+        show_counts: bool,
+        sort: SortMode,
+        reverse: bool,
+    ) -> std::io::Result<()> {
+        let children = self.sorted_children(sort, reverse);
+        let last = children.len().saturating_sub(1);
+        for (i, (name, child)) in children.into_iter().enumerate() {
+            let (branch, indent) = if i == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            writeln!(
+                out,
+                "{}{}{}",
+                prefix,
+                branch,
+                child.label(name, show_counts)
+            )?;
+            child.write_children(
+                out,
+                &format!("{}{}", prefix, indent),
+                show_counts,
+                sort,
+                reverse,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn label(&self, name: &str, show_counts: bool) -> String {
+        if show_counts {
+            format!("{} ({})", name, self.count)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn render(tree: &TagTree, show_counts: bool) -> String {
+        let mut out = Vec::new();
+        tree.write(&mut out, show_counts, SortMode::Alpha, false)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn draws_nested_tags() {
+        let tree =
+            TagTree::from_counts([("#project/work/q1", 1), ("project/home", 2), ("solo", 1)]);
+        assert_eq!(
+            render(&tree, false),
+            "project\n├── home\n└── work\n    └── q1\nsolo\n"
+        );
+        assert_eq!(
+            render(&tree, true),
+            "project (3)\n├── home (2)\n└── work (1)\n    └── q1 (1)\nsolo (1)\n"
+        );
+    }
+
+    #[test]
+    fn orders_siblings_by_sort_mode() {
+        let tree = TagTree::from_counts([("a/x", 1), ("a/y", 3), ("b", 2)]);
+        let mut out = Vec::new();
+        tree.write(&mut out, true, SortMode::Freq, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a (4)\n├── y (3)\n└── x (1)\nb (2)\n"
+        );
+
+        let mut out = Vec::new();
+        tree.write(&mut out, false, SortMode::Alpha, true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b\na\n├── y\n└── x\n");
+    }
+
+    #[test]
+    fn counts_each_note_once_per_node() {
+        let notes = |names: &[&str]| names.iter().map(PathBuf::from).collect();
+        let tag_files = TagFiles::from([
+            ("project".to_string(), notes(&["a.md", "b.md"])),
+            ("project/alpha".to_string(), notes(&["a.md"])),
+        ]);
+        let tree = TagTree::from_tag_files(&tag_files);
+
+        assert_eq!(tree.count, 2);
+        assert_eq!(tree.children["project"].count, 2);
+        assert_eq!(tree.children["project"].children["alpha"].count, 1);
+    }
+}
@@ -0,0 +1,70 @@
+//! A vault as a single value, for programs embedding this crate.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    collect_paths_with, collect_tags, inline_tags, remove_hash, ScanOptions, VaultError,
+    WalkOptions,
+};
+
+/// An Obsidian vault rooted at a directory.
+///
+/// ```no_run
+/// use obsidian_get_tags::Vault;
+///
+/// let vault = Vault::new("/path/to/vault");
+/// for tag in vault.frontmatter_tags()? {
+///     println!("{}", tag);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Vault {
+    root: PathBuf,
+    walk: WalkOptions,
+}
+
+impl Vault {
+    /// A vault walked with the default [`WalkOptions`].
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_options(root, WalkOptions::default())
+    }
+
+    pub fn with_options(root: impl Into<PathBuf>, walk: WalkOptions) -> Self {
+        Vault {
+            root: root.into(),
+            walk,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The notes of the vault, see [`collect_paths_with`].
+    pub fn note_paths(&self) -> Result<Vec<PathBuf>, VaultError> {
+        collect_paths_with(&self.root, &self.walk)
+    }
+
+    /// Every front matter tag of the vault, without `#`. Notes whose front
+    /// matter cannot be parsed are logged and skipped.
+    pub fn frontmatter_tags(&self) -> Result<HashSet<String>> {
+        let tags = collect_tags(&self.note_paths()?, ScanOptions::default())?;
+        Ok(tags
+            .iter()
+            .map(|tag| remove_hash(tag).to_string())
+            .collect())
+    }
+
+    /// Every inline `#tag` of the vault, without `#`, found by the built-in
+    /// scanner. Unreadable notes are logged and skipped.
+    pub fn inline_tags(&self) -> Result<HashSet<String>> {
+        Ok(inline_tags::collect_inline_tags(&self.note_paths()?)
+            .iter()
+            .map(|tag| remove_hash(tag).to_string())
+            .collect())
+    }
+}
@@ -0,0 +1,262 @@
+//! Keeping the tag set of a vault up to date as its notes change.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{relative_path, remove_hash, scan_file, ScanOptions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WatchFormat {
+    /// `+tag` and `-tag` lines
+    Plain,
+    /// One JSON event object per line
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Add,
+    Remove,
+}
+
+/// A tag appearing in, or disappearing from, the whole vault.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TagEvent {
+    pub event: EventKind,
+    pub tag: String,
+    /// The vault-relative note whose change caused the event
+    pub file: PathBuf,
+}
+
+impl TagEvent {
+    pub fn to_line(&self, format: WatchFormat) -> String {
+        match format {
+            WatchFormat::Plain => {
+                let sign = match self.event {
+                    EventKind::Add => '+',
+                    EventKind::Remove => '-',
+                };
+                format!("{}{}", sign, self.tag)
+            }
+            WatchFormat::Json => serde_json::to_string(self).expect("events serialize"),
+        }
+    }
+}
+
+/// The tags of every note of a vault, updated one note at a time.
+#[derive(Debug, Default)]
+pub struct TagIndex {
+    root: PathBuf,
+    files: HashMap<PathBuf, BTreeSet<String>>,
+    /// In how many notes each tag is used
+    counts: BTreeMap<String, usize>,
+}
+
+impl TagIndex {
+    /// Scans `paths`, the notes of the vault at `root`.
+    pub fn build(root: &Path, paths: &[PathBuf], options: ScanOptions) -> Self {
+        let mut index = TagIndex {
+            root: root.to_path_buf(),
+            ..Default::default()
+        };
+        let scanned: Vec<_> = paths
+            .par_iter()
+            .map(|path| (path.clone(), note_tags(path, options)))
+            .collect();
+        for (path, tags) in scanned {
+            index.replace(&path, tags);
+        }
+        index
+    }
+
+    /// An `Add` event for every tag, attributed to the first note using it.
+    pub fn initial_events(&self) -> Vec<TagEvent> {
+        let mut first_file: BTreeMap<&str, &Path> = BTreeMap::new();
+        for (path, tags) in &self.files {
+            for tag in tags {
+                first_file
+                    .entry(tag)
+                    .and_modify(|file| *file = (*file).min(path.as_path()))
+                    .or_insert(path);
+            }
+        }
+        first_file
+            .into_iter()
+            .map(|(tag, path)| self.event(EventKind::Add, tag, path))
+            .collect()
+    }
+
+    /// Rescans `path` (forgetting it when it no longer exists) and returns the
+    /// tags the vault gained and lost because of it.
+    pub fn update(&mut self, path: &Path, options: ScanOptions) -> Vec<TagEvent> {
+        let tags = if path.is_file() {
+            note_tags(path, options)
+        } else {
+            BTreeSet::new()
+        };
+        let (added, removed) = self.replace(path, tags);
+        removed
+            .iter()
+            .map(|tag| self.event(EventKind::Remove, tag, path))
+            .chain(
+                added
+                    .iter()
+                    .map(|tag| self.event(EventKind::Add, tag, path)),
+            )
+            .collect()
+    }
+
+    /// Rescans the notes at or below `dir`, a path that is no file: a
+    /// directory moved in or out may come as a single event, without its
+    /// notes. Notes found below it are kept when `keep` accepts them.
+    pub fn update_dir(
+        &mut self,
+        dir: &Path,
+        options: ScanOptions,
+        keep: impl Fn(&Path) -> bool,
+    ) -> Vec<TagEvent> {
+        let mut notes: BTreeSet<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        if dir.is_dir() {
+            notes.extend(
+                WalkDir::new(dir)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .filter(|entry| entry.file_type().is_file())
+                    .map(|entry| entry.into_path())
+                    .filter(|path| keep(path)),
+            );
+        }
+        let mut events = Vec::new();
+        for note in notes {
+            events.extend(self.update(&note, options));
+        }
+        events
+    }
+
+    /// Every tag of the vault, sorted.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.counts.keys().map(String::as_str)
+    }
+
+    /// Sets the tags of `path`, returning the tags new to the vault and those
+    /// no note uses anymore.
+    fn replace(&mut self, path: &Path, tags: BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+        let old = if tags.is_empty() {
+            self.files.remove(path).unwrap_or_default()
+        } else {
+            self.files
+                .insert(path.to_path_buf(), tags.clone())
+                .unwrap_or_default()
+        };
+
+        let mut added = Vec::new();
+        for tag in tags.difference(&old) {
+            let count = self.counts.entry(tag.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                added.push(tag.clone());
+            }
+        }
+        let mut removed = Vec::new();
+        for tag in old.difference(&tags) {
+            if let Some(count) = self.counts.get_mut(tag) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(tag);
+                    removed.push(tag.clone());
+                }
+            }
+        }
+        (added, removed)
+    }
+
+    fn event(&self, event: EventKind, tag: &str, path: &Path) -> TagEvent {
+        TagEvent {
+            event,
+            tag: tag.to_string(),
+            file: relative_path(&self.root, path),
+        }
+    }
+}
+
+fn note_tags(path: &Path, options: ScanOptions) -> BTreeSet<String> {
+    scan_file(path, options)
+        .iter()
+        .map(|tag| remove_hash(tag).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_common::fixture_vault;
+
+    #[test]
+    fn updates_report_vault_wide_changes() {
+        let root = fixture_vault(&[
+            ("a.md", "---\ntags: [shared, only-a]\n---\n"),
+            ("b.md", "---\ntags: [shared]\n---\n"),
+        ]);
+        let (a, b) = (root.join("a.md"), root.join("b.md"));
+
+        let options = ScanOptions::default();
+        let mut index = TagIndex::build(&root, &[a.clone(), b.clone()], options);
+        let initial: Vec<_> = index
+            .initial_events()
+            .iter()
+            .map(|e| e.to_line(WatchFormat::Plain))
+            .collect();
+
+        std::fs::write(&a, "---\ntags: [fresh]\n---\n").unwrap();
+        let changed = index.update(&a, options);
+        std::fs::remove_file(&b).unwrap();
+        let deleted = index.update(&b, options);
+
+        assert_eq!(initial, ["+only-a", "+shared"]);
+        let lines: Vec<_> = changed
+            .iter()
+            .map(|e| e.to_line(WatchFormat::Plain))
+            .collect();
+        assert_eq!(lines, ["-only-a", "+fresh"]);
+        assert_eq!(
+            deleted[0].to_line(WatchFormat::Json),
+            r#"{"event":"remove","tag":"shared","file":"b.md"}"#
+        );
+        assert_eq!(index.tags().collect::<Vec<_>>(), ["fresh"]);
+    }
+
+    #[test]
+    fn moved_directories_take_their_notes_along() {
+        let root = fixture_vault(&[("old/a.md", "---\ntags: [moved]\n---\n")]);
+        let (old, new) = (root.join("old"), root.join("new"));
+
+        let options = ScanOptions::default();
+        let mut index = TagIndex::build(&root, &[old.join("a.md")], options);
+        std::fs::rename(&old, &new).unwrap();
+        let lines = |events: Vec<TagEvent>| -> Vec<_> {
+            events
+                .iter()
+                .map(|e| e.to_line(WatchFormat::Plain))
+                .collect()
+        };
+        let gone = lines(index.update_dir(&old, options, |_| true));
+        let emptied: Vec<_> = index.tags().map(String::from).collect();
+        let back = lines(index.update_dir(&new, options, |_| true));
+
+        assert_eq!(gone, ["-moved"]);
+        assert!(emptied.is_empty());
+        assert_eq!(back, ["+moved"]);
+        assert_eq!(index.tags().collect::<Vec<_>>(), ["moved"]);
+    }
+}
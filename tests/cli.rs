@@ -0,0 +1,802 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+use common::{fixture_vault, TempVault};
+
+fn run(vault: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+        .arg("--path")
+        .arg(vault)
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn broken_front_matter_warns_and_fails_only_when_strict() {
+    let vault = fixture_vault(&[
+        ("good.md", "---\ntags: [ok]\n---\n"),
+        ("bad.md", "---\ntags: 42\n---\n"),
+    ]);
+
+    let lenient = run(&vault, &[]);
+    let strict = run(&vault, &["--strict"]);
+    let warned = run(&vault, &["--warn-errors"]);
+
+    assert!(lenient.status.success());
+    assert_eq!(String::from_utf8_lossy(&lenient.stdout), "ok\n");
+    let stderr = String::from_utf8_lossy(&lenient.stderr);
+    assert!(stderr.contains("bad.md"), "{}", stderr);
+
+    assert_eq!(strict.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&strict.stdout), "ok\n");
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+    assert!(
+        stderr.contains("1 file(s) could not be parsed"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\nbad.md: Expected 'tags' to be an array or a string"),
+        "{}",
+        stderr
+    );
+
+    assert!(warned.status.success());
+    assert_eq!(String::from_utf8_lossy(&warned.stdout), "ok\n");
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert!(
+        stderr.contains("\nbad.md: Expected 'tags' to be an array or a string"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("could not be parsed"), "{}", stderr);
+}
+
+#[test]
+fn report_errors_lists_broken_files() {
+    let vault = fixture_vault(&[
+        ("good.md", "---\ntags: [ok]\n---\n"),
+        ("sub/bad.md", "---\ntags: 42\n---\n"),
+    ]);
+
+    let output = run(&vault, &["--report-errors"]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let row = stderr
+        .lines()
+        .find(|line| line.starts_with("sub/bad.md"))
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert!(row.contains("InvalidTagsType"), "{}", row);
+}
+
+#[test]
+fn min_and_max_count_filter_by_notes() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [common, rare]\n---\n"),
+        ("b.md", "---\ntags: [common]\n---\n#rare #inline #inline\n"),
+        ("c.md", "---\ntags: [common]\n---\n"),
+    ]);
+
+    let output = run(&vault, &["--inline", "--min-count", "2"]);
+    let capped = run(
+        &vault,
+        &["--inline", "--min-count", "2", "--max-count", "2"],
+    );
+    let zero = run(&vault, &["--min-count", "0"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "common\nrare\n");
+    assert_eq!(String::from_utf8_lossy(&capped.stdout), "rare\n");
+    assert_eq!(zero.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&zero.stderr);
+    assert!(stderr.contains("must be at least 1"), "{}", stderr);
+}
+
+#[test]
+fn several_vaults_are_merged_once() {
+    let work = fixture_vault(&[("a.md", "---\ntags: [work, shared]\n---\n")]);
+    let home = fixture_vault(&[("a.md", "---\ntags: [home, shared]\n---\n")]);
+
+    let output = run(
+        &work,
+        &[
+            "--path",
+            home.to_str().unwrap(),
+            "--path",
+            work.to_str().unwrap(),
+            "--count",
+        ],
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "2\tshared\n1\thome\n1\twork\n"
+    );
+}
+
+#[test]
+fn drawings_only_give_front_matter_tags() {
+    let vault = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/drawings");
+    let args = ["--inline", "--extension", "md", "--extension", "canvas"];
+
+    let skipped = run(&vault, &args);
+    let scanned = run(&vault, &[&args[..], &["--no-skip-excalidraw"]].concat());
+
+    assert!(skipped.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&skipped.stdout),
+        "design\ndrawing\nexcalidraw\n"
+    );
+    let scanned = String::from_utf8_lossy(&scanned.stdout);
+    assert!(scanned.lines().any(|tag| tag == "ffffff"), "{}", scanned);
+    assert!(scanned.lines().any(|tag| tag == "canvas"), "{}", scanned);
+}
+
+#[test]
+fn case_variants_are_merged() {
+    let vault = fixture_vault(&[
+        ("upper.md", "---\ntags: [TODO, Work]\n---\n"),
+        ("lower.md", "---\ntags: [todo]\n---\n"),
+    ]);
+
+    let sensitive = run(&vault, &[]);
+    let insensitive = run(&vault, &["--case-insensitive"]);
+    let lowercase = run(&vault, &["--lowercase", "--count"]);
+
+    assert_eq!(
+        String::from_utf8_lossy(&sensitive.stdout),
+        "TODO\nWork\ntodo\n"
+    );
+    assert_eq!(String::from_utf8_lossy(&insensitive.stdout), "TODO\nWork\n");
+    assert_eq!(
+        String::from_utf8_lossy(&lowercase.stdout),
+        "2\ttodo\n1\twork\n"
+    );
+}
+
+#[test]
+fn output_file_and_its_directories_are_created() {
+    let vault = fixture_vault(&[("a.md", "---\ntags: [x]\n---\n")]);
+    let target = vault.join("out/nested/tags.txt");
+
+    let written = run(&vault, &["--output", target.to_str().unwrap()]);
+    let contents = std::fs::read_to_string(&target);
+    run(&vault, &["--output", target.to_str().unwrap()]);
+    let replaced = std::fs::read_to_string(&target);
+    run(&vault, &["--output", target.to_str().unwrap(), "--append"]);
+    let appended = std::fs::read_to_string(&target);
+    let to_dir = run(&vault, &["--output", vault.to_str().unwrap()]);
+
+    assert!(written.status.success());
+    assert!(written.stdout.is_empty());
+    assert_eq!(contents.unwrap(), "x\n");
+    assert_eq!(replaced.unwrap(), "x\n");
+    assert_eq!(appended.unwrap(), "x\nx\n");
+    assert!(!to_dir.status.success());
+    let stderr = String::from_utf8_lossy(&to_dir.stderr);
+    assert!(stderr.contains("is a directory"), "{}", stderr);
+}
+
+#[test]
+fn max_depth_limits_both_passes() {
+    let vault = fixture_vault(&[
+        ("top.md", "---\ntags: [top]\n---\n#top-inline\n"),
+        ("a/mid.md", "---\ntags: [mid]\n---\n#mid-inline\n"),
+        ("a/b/deep.md", "---\ntags: [deep]\n---\n#deep-inline\n"),
+    ]);
+
+    let front_matter = run(&vault, &["--max-depth", "2"]);
+    let inline = run(&vault, &["--max-depth", "2", "--inline"]);
+    let rg = run(&vault, &["--max-depth", "2", "--rg"]);
+
+    assert_eq!(String::from_utf8_lossy(&front_matter.stdout), "mid\ntop\n");
+    let both = "mid\nmid-inline\ntop\ntop-inline\n";
+    assert_eq!(String::from_utf8_lossy(&inline.stdout), both);
+    assert_eq!(String::from_utf8_lossy(&rg.stdout), both);
+}
+
+#[test]
+fn singleton_tags_name_their_note() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [shared, a-typo-tag]\n---\n"),
+        ("sub/b.md", "---\ntags: [shared, lonely]\n---\n"),
+    ]);
+
+    let output = run(&vault, &["--singleton-tags"]);
+    let by_length = run(&vault, &["--singleton-tags", "--sort", "length"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "a-typo-tag\ta.md\nlonely\t{}\n",
+            Path::new("sub").join("b.md").display()
+        )
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&by_length.stdout),
+        format!(
+            "lonely\t{}\na-typo-tag\ta.md\n",
+            Path::new("sub").join("b.md").display()
+        )
+    );
+}
+
+#[cfg(unix)]
+/// A directory holding an `rg` that checks its flags all come before `--`,
+/// then prints `path NUL #tag` lines like `rg --with-filename --null -o`.
+#[cfg(unix)]
+fn fake_rg() -> TempVault {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = r#"#!/bin/sh
+while [ $# -gt 0 ] && [ "$1" != "--" ]; do
+    case "$1" in
+    --with-filename) named=1 ;;
+    --null) null=1 ;;
+    esac
+    shift
+done
+[ $# -gt 0 ] && [ "$named$null" = 11 ] || { echo "bad rg arguments" >&2; exit 2; }
+shift
+for target in "$@"; do
+    case "$target" in -*) echo "flag after --: $target" >&2; exit 2 ;; esac
+done
+exec grep -rHoZ -E '#[A-Za-z][A-Za-z0-9_/-]*' -- "$@"
+"#;
+    let bin = fixture_vault(&[("rg", script)]);
+    std::fs::set_permissions(bin.join("rg"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    bin
+}
+
+/// [`run`] with `bin` first on `PATH`.
+#[cfg(unix)]
+fn run_with_path(bin: &Path, vault: &Path, args: &[&str]) -> Output {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(
+        std::iter::once(bin.to_path_buf()).chain(std::env::split_paths(&path)),
+    )
+    .unwrap();
+    Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+        .arg("--path")
+        .arg(vault)
+        .args(args)
+        .env("PATH", path)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn rg_gets_its_flags_before_the_paths() {
+    let vault = fixture_vault(&[("a.md", "---\ntags: [front]\n---\n#inline text\n")]);
+    let bin = fake_rg();
+
+    let output = run_with_path(&bin, &vault, &["--rg"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("rg failed"), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "front\ninline\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn rg_only_searches_the_walked_notes() {
+    let vault = fixture_vault(&[
+        ("note.md", "#kept\n"),
+        ("templates/t.md", "#template\n"),
+        (".obsidian/workspace.md", "#config\n"),
+        ("x.txt", "#text\n"),
+    ]);
+    let bin = fake_rg();
+
+    let output = run_with_path(&bin, &vault, &["--rg", "--exclude", "templates/**"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "kept\n");
+}
+
+#[test]
+fn failing_rg_keeps_front_matter_tags() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let vault = fixture_vault(&[("a.md", "---\ntags: [kept]\n---\n#lost\n")]);
+    let empty = fixture_vault(&[("a.md", "#lost\n")]);
+    let bin = fixture_vault(&[("rg", "#!/bin/sh\nexit 2\n")]);
+    std::fs::set_permissions(bin.join("rg"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let run_rg = |vault: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+            .arg("--path")
+            .arg(vault)
+            .arg("--rg")
+            .env("PATH", &bin)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    };
+
+    let output = run_rg(&vault);
+    let nothing = run_rg(&empty);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "kept\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("rg failed"), "{}", stderr);
+    assert_eq!(nothing.status.code(), Some(1));
+}
+
+#[cfg(unix)]
+#[test]
+fn ignore_file_also_hides_inline_tags() {
+    let vault = fixture_vault(&[
+        (".get-tags-ignore", "templates/\n"),
+        ("note.md", "#kept\n"),
+        (
+            "templates/daily.md",
+            "---\ntags: [template]\n---\n#ignored\n",
+        ),
+    ]);
+
+    let inline = run(&vault, &["--inline"]);
+    // rg is only given the notes left by the ignore file
+    let bin = fake_rg();
+    let rg = run_with_path(&bin, &vault, &["--rg"]);
+
+    assert_eq!(String::from_utf8_lossy(&inline.stdout), "kept\n");
+    assert_eq!(String::from_utf8_lossy(&rg.stdout), "kept\n");
+}
+
+#[test]
+fn dry_run_lists_the_notes_to_scan() {
+    let vault = fixture_vault(&[
+        ("note.md", "---\ntags: 42\n---\n"),
+        ("drafts/wip.md", ""),
+        ("image.png", ""),
+    ]);
+
+    let output = run(&vault, &["--dry-run", "--strict"]);
+    let excluded = run(&vault, &["--dry-run", "--exclude", "drafts/**"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}\nnote.md\n",
+            Path::new("drafts").join("wip.md").display()
+        )
+    );
+    assert_eq!(String::from_utf8_lossy(&excluded.stdout), "note.md\n");
+}
+
+#[test]
+fn exit_status_tells_usage_from_assertions() {
+    let vault = fixture_vault(&[("untagged.md", "no tags\n")]);
+
+    let missing = run(&vault.join("missing"), &[]);
+    let not_a_directory = run(&vault.join("untagged.md"), &[]);
+    let empty = run(&vault, &[]);
+    let failed = run(&vault, &["--fail-if-empty"]);
+
+    assert_eq!(missing.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("no such vault"), "{}", stderr);
+    assert_eq!(not_a_directory.status.code(), Some(2));
+    assert!(empty.status.success());
+    assert_eq!(failed.status.code(), Some(1));
+}
+
+#[test]
+fn per_file_lists_untagged_notes_when_asked() {
+    let vault = fixture_vault(&[("a.md", "---\ntags: [x, y]\n---\n"), ("b.md", "no tags\n")]);
+
+    let tagged = run(&vault, &["--per-file"]);
+    let all = run(&vault, &["--per-file", "--include-empty"]);
+    let json = run(&vault, &["--per-file", "--format", "json-compact"]);
+
+    assert_eq!(String::from_utf8_lossy(&tagged.stdout), "a.md: x, y\n");
+    assert_eq!(String::from_utf8_lossy(&all.stdout), "a.md: x, y\nb.md: \n");
+    assert_eq!(
+        String::from_utf8_lossy(&json.stdout),
+        "[{\"file\":\"a.md\",\"tags\":[\"x\",\"y\"]}]\n"
+    );
+}
+
+#[test]
+fn notes_subcommand_finds_notes_by_tag() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [project/alpha, meeting]\n---\n"),
+        ("b.md", "---\ntags: [project]\n---\n"),
+        ("c.md", "---\ntags: [projects]\n---\n#meeting\n"),
+    ]);
+
+    let nested = run(&vault, &["notes", "#project"]);
+    let exact = run(&vault, &["notes", "project", "--exact"]);
+    let any = run(&vault, &["--inline", "notes", "project", "meeting"]);
+    let all = run(
+        &vault,
+        &["--inline", "notes", "project", "meeting", "--all"],
+    );
+    let files = run(&vault, &["files", "project"]);
+    let tags = run(&vault, &["tags"]);
+
+    assert_eq!(String::from_utf8_lossy(&nested.stdout), "a.md\nb.md\n");
+    assert_eq!(String::from_utf8_lossy(&exact.stdout), "b.md\n");
+    assert_eq!(String::from_utf8_lossy(&any.stdout), "a.md\nb.md\nc.md\n");
+    assert_eq!(String::from_utf8_lossy(&all.stdout), "a.md\n");
+    assert_eq!(files.stdout, nested.stdout);
+    assert_eq!(
+        String::from_utf8_lossy(&tags.stdout),
+        "meeting\nproject\nproject/alpha\nprojects\n"
+    );
+}
+
+#[test]
+fn diff_subcommand_compares_two_vaults() {
+    let old = fixture_vault(&[("a.md", "---\ntags: [gone, kept]\n---\n")]);
+    let new = fixture_vault(&[("b.md", "---\ntags: [kept]\n---\n#new\n")]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+        .args(["--inline", "diff", "--diff-per-file"])
+        .args([&old, &new])
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "-gone: a.md\n kept\n+new: b.md\n"
+    );
+}
+
+#[test]
+fn cache_file_is_reused_for_unchanged_notes() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [kept]\n---\n#body\n"),
+        ("b.md", "---\ntags: [before]\n---\n"),
+    ]);
+    let cache_dir = fixture_vault(&[]);
+    let cache = cache_dir.join("cache.json");
+    let cache_arg = format!("--cache={}", cache.display());
+    let args = ["--inline", &cache_arg];
+
+    let first = run(&vault, &args);
+    let stored = std::fs::read_to_string(&cache).unwrap();
+    // only a stale entry would bring this tag back
+    std::fs::write(&cache, stored.replace("\"kept\"", "\"from-cache\"")).unwrap();
+    std::fs::write(vault.join("b.md"), "---\ntags: [after, longer]\n---\n").unwrap();
+    let second = run(&vault, &args);
+    let updated = std::fs::read_to_string(&cache).unwrap();
+    // as from a shell alias with --cache
+    let bypassed = run(&vault, &["--inline", &cache_arg, "--no-cache"]);
+
+    assert_eq!(
+        String::from_utf8_lossy(&first.stdout),
+        "before\nbody\nkept\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&second.stdout),
+        "after\nbody\nfrom-cache\nlonger\n"
+    );
+    assert!(updated.contains("\"after\""), "{}", updated);
+    assert!(!updated.contains("\"before\""), "{}", updated);
+    assert_eq!(
+        String::from_utf8_lossy(&bypassed.stdout),
+        "after\nbody\nkept\nlonger\n"
+    );
+}
+
+#[test]
+fn index_rescans_changed_notes_until_rebuilt() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [kept]\n---\n"),
+        ("b.md", "---\ntags: [before]\n---\n"),
+    ]);
+    let index_dir = fixture_vault(&[]);
+    let index = index_dir.join("index.sqlite");
+    let index_arg = format!("--index={}", index.display());
+
+    let first = run(&vault, &[&index_arg]);
+    // only a stale row would bring this tag back
+    rusqlite::Connection::open(&index)
+        .unwrap()
+        .execute(
+            "UPDATE notes SET tags = '[\"from-index\"]' WHERE path = 'a.md'",
+            [],
+        )
+        .unwrap();
+    std::fs::write(vault.join("b.md"), "---\ntags: [after, longer]\n---\n").unwrap();
+    let second = run(&vault, &[&index_arg]);
+    let rebuilt = run(&vault, &[&index_arg, "--rebuild-index"]);
+
+    assert_eq!(String::from_utf8_lossy(&first.stdout), "before\nkept\n");
+    assert_eq!(
+        String::from_utf8_lossy(&second.stdout),
+        "after\nfrom-index\nlonger\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&rebuilt.stdout),
+        "after\nkept\nlonger\n"
+    );
+}
+
+#[test]
+fn completions_subcommand_covers_every_shell() {
+    for shell in ["bash", "zsh", "fish", "powershell"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+            .args(["completions", shell])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{}", shell);
+        let script = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            script.contains("obsidian-get-tags"),
+            "{}: {}",
+            shell,
+            script
+        );
+    }
+}
+
+#[test]
+fn completions_are_generated() {
+    let output = Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+        .args(["--generate-completions", "bash"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let script = String::from_utf8_lossy(&output.stdout);
+    assert!(script.contains("--follow-symlinks"), "{}", script);
+    assert!(script.contains("obsidian-get-tags"), "{}", script);
+}
+
+#[test]
+fn thread_count_does_not_change_the_tags() {
+    let vault = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vault");
+
+    let default = run(&vault, &["--inline"]);
+    let single = run(&vault, &["--inline", "--threads", "1"]);
+
+    assert!(single.status.success());
+    assert_eq!(single.stdout, default.stdout);
+}
+
+#[test]
+fn hashed_and_bare_tags_are_one_tag() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [\"#work\"]\n---\n"),
+        ("b.md", "---\ntags: [work]\n---\n#work\n"),
+    ]);
+
+    let plain = run(&vault, &["--inline"]);
+    let counts = run(&vault, &["--inline", "--count"]);
+
+    assert_eq!(String::from_utf8_lossy(&plain.stdout), "work\n");
+    assert_eq!(String::from_utf8_lossy(&counts.stdout), "2\twork\n");
+}
+
+#[test]
+fn with_hash_prefixes_every_tag() {
+    let vault = fixture_vault(&[("a.md", "---\ntags: [front]\n---\n#inline\n")]);
+
+    let default = run(&vault, &["--inline"]);
+    let hashed = run(&vault, &["--inline", "--with-hash"]);
+    let counts = run(&vault, &["--inline", "--count", "--with-hash", "--no-hash"]);
+
+    assert_eq!(String::from_utf8_lossy(&default.stdout), "front\ninline\n");
+    assert_eq!(String::from_utf8_lossy(&hashed.stdout), "#front\n#inline\n");
+    assert_eq!(
+        String::from_utf8_lossy(&counts.stdout),
+        "1\tfront\n1\tinline\n"
+    );
+}
+
+#[test]
+fn streamed_tags_are_the_batch_tags() {
+    let vault = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let args = ["--inline"];
+
+    let batch = run(&vault, &args);
+    let streamed = run(&vault, &[&args[..], &["--stream"]].concat());
+    let unsorted = run(&vault, &[&args[..], &["--sort", "none"]].concat());
+
+    assert!(streamed.status.success());
+    let lines = |output: &Output| {
+        let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        lines
+    };
+    assert!(!lines(&batch).is_empty());
+    assert_eq!(lines(&streamed), lines(&batch));
+    assert_eq!(lines(&unsorted), lines(&batch));
+}
+
+#[test]
+fn relative_to_a_parent_or_a_sibling_of_the_vault() {
+    let root = fixture_vault(&[
+        ("sibling/.keep", ""),
+        ("vault/a.md", "---\ntags: [x]\n---\n"),
+    ]);
+    let vault = root.join("vault");
+    let parent = format!("--relative-to={}", root.display());
+    let sibling = format!("--relative-to={}", root.join("sibling").display());
+
+    let from_parent = run(&vault, &["--files", "--format", "json-compact", &parent]);
+    let from_sibling = run(&vault, &["--files", "--format", "json", &sibling]);
+    let absolute = run(&vault, &["--files", "--absolute"]);
+    let canonical = vault.join("a.md").canonicalize().unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&from_parent.stdout).trim(),
+        r#"[{"tag":"x","files":["vault/a.md"]}]"#
+    );
+    // outside of the base, the note is printed absolute with a warning
+    assert!(from_sibling.status.success());
+    let stdout = String::from_utf8_lossy(&from_sibling.stdout);
+    assert!(stdout.contains(&*canonical.to_string_lossy()), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&from_sibling.stderr);
+    assert!(stderr.contains("not under"), "{}", stderr);
+    let stdout = String::from_utf8_lossy(&absolute.stdout);
+    assert!(stdout.contains(&*canonical.to_string_lossy()), "{}", stdout);
+}
+
+#[test]
+fn files_from_stdin_are_taken_from_the_vault() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [a]\n---\n"),
+        ("sub/b.md", "---\ntags: [b]\n---\n"),
+        ("c.md", "---\ntags: [c]\n---\n"),
+        ("notes.txt", "---\ntags: [txt]\n---\n"),
+    ]);
+    let listed = |args: &[&str], input: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_obsidian-get-tags"))
+            .arg("--path")
+            .arg(&vault)
+            .arg("--files-from-stdin")
+            .args(args)
+            .env_remove("RUST_LOG")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let lines = listed(&[], "a.md\nsub/b.md\nnotes.txt\n");
+    let nul = listed(&["--null", "--print0"], "a.md\0sub/b.md\0");
+    let counted = listed(&["-z", "--count"], "a.md\0c.md\0");
+
+    assert_eq!(String::from_utf8_lossy(&lines.stdout), "a\nb\n");
+    let stderr = String::from_utf8_lossy(&lines.stderr);
+    assert!(stderr.contains("notes.txt: not a note"), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&nul.stdout), "a\0b\0");
+    assert_eq!(String::from_utf8_lossy(&counted.stdout), "1\ta\n1\tc\n");
+}
+
+#[test]
+fn prefix_keeps_nested_tags_with_sort_and_count() {
+    let vault = fixture_vault(&[
+        (
+            "a.md",
+            "---\ntags: [project, project/alpha, projectx]\n---\n",
+        ),
+        ("b.md", "---\ntags: [project/alpha]\n---\n#project/beta\n"),
+    ]);
+
+    let counts = run(
+        &vault,
+        &[
+            "--inline", "--prefix", "#project", "--count", "--sort", "alpha",
+        ],
+    );
+    let by_freq = run(
+        &vault,
+        &["--inline", "--prefix", "project", "--sort", "freq"],
+    );
+
+    assert_eq!(
+        String::from_utf8_lossy(&counts.stdout),
+        "1\tproject\n2\tproject/alpha\n1\tproject/beta\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&by_freq.stdout),
+        "project/alpha\nproject\nproject/beta\n"
+    );
+}
+
+#[test]
+fn tree_counts_notes_like_expanded_parents() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [project, project/alpha, zeta]\n---\n"),
+        (
+            "b.md",
+            "---\ntags: [project/beta, project/alpha, zeta]\n---\n",
+        ),
+        ("c.md", "---\ntags: [zeta]\n---\n"),
+    ]);
+
+    let tree = run(&vault, &["--tree", "--count"]);
+    let expanded = run(&vault, &["--count", "--expand-parents"]);
+    let names = run(&vault, &["--tree"]);
+    let yaml = run(&vault, &["--tree", "--format", "yaml"]);
+
+    assert_eq!(
+        String::from_utf8_lossy(&tree.stdout),
+        "zeta (3)\nproject (2)\n├── alpha (2)\n└── beta (1)\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&expanded.stdout),
+        "3\tzeta\n2\tproject\n2\tproject/alpha\n1\tproject/beta\n"
+    );
+    // without counts the siblings stay in alphabetical order
+    assert_eq!(
+        String::from_utf8_lossy(&names.stdout),
+        "project\n├── alpha\n└── beta\nzeta\n"
+    );
+    assert!(
+        String::from_utf8_lossy(&yaml.stdout).contains("project:\n  _count: 2\n"),
+        "{}",
+        String::from_utf8_lossy(&yaml.stdout)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_errors_are_logged_with_or_without_gitignore() {
+    let vault = fixture_vault(&[("a.md", "---\ntags: [ok]\n---\n")]);
+    std::os::unix::fs::symlink(vault.join("missing"), vault.join("gone.md")).unwrap();
+
+    let plain = run(&vault, &["--follow-symlinks"]);
+    let ignoring = run(&vault, &["--follow-symlinks", "--respect-gitignore"]);
+
+    for output in [plain, ignoring] {
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("gone.md"), "{}", stderr);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn files_for_tag_follows_case_and_rg_flags() {
+    let vault = fixture_vault(&[
+        ("a.md", "---\ntags: [work]\n---\n"),
+        ("b.md", "Met at #Work today\n"),
+        ("c.md", "```\n#Work\n```\n"),
+    ]);
+    let bin = fake_rg();
+
+    let exact = run(&vault, &["--files-for-tag", "work"]);
+    let folded = run(&vault, &["--files-for-tag", "work", "--case-insensitive"]);
+    // the fake rg, unlike the built-in scanner, reads code blocks too
+    let rg = run_with_path(
+        &bin,
+        &vault,
+        &["--files-for-tag", "work", "--case-insensitive", "--rg"],
+    );
+
+    assert_eq!(String::from_utf8_lossy(&exact.stdout), "a.md\n");
+    assert_eq!(String::from_utf8_lossy(&folded.stdout), "a.md\nb.md\n");
+    assert_eq!(String::from_utf8_lossy(&rg.stdout), "a.md\nb.md\nc.md\n");
+}
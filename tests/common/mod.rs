@@ -0,0 +1,46 @@
+//! Test helpers, shared by the integration tests and, through `#[path]`, the
+//! unit tests of the library.
+
+use std::ffi::OsStr;
+use std::ops::Deref;
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// A temporary directory used as a path. It is removed, along with everything
+/// in it, when dropped, so a failing assertion doesn't leave it behind.
+pub struct TempVault(TempDir);
+
+impl Deref for TempVault {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl AsRef<Path> for TempVault {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+impl AsRef<OsStr> for TempVault {
+    fn as_ref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+/// Writes `files` under a fresh temporary directory.
+pub fn fixture_vault(files: &[(&str, &str)]) -> TempVault {
+    let dir = tempfile::Builder::new()
+        .prefix("obsidian-get-tags-")
+        .tempdir()
+        .unwrap();
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    TempVault(dir)
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use obsidian_get_tags::{load_tags_with, Vault, VaultError};
+
+fn fixture() -> Vault {
+    Vault::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vault"))
+}
+
+fn set(tags: &[&str]) -> HashSet<String> {
+    tags.iter().map(|tag| tag.to_string()).collect()
+}
+
+#[test]
+fn note_paths_skip_hidden_and_non_markdown_files() {
+    let vault = fixture();
+    let mut paths: Vec<PathBuf> = vault
+        .note_paths()
+        .unwrap()
+        .iter()
+        .map(|path| path.strip_prefix(vault.root()).unwrap().to_path_buf())
+        .collect();
+    paths.sort();
+
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("daily.md"),
+            PathBuf::from("projects/alpha.md")
+        ]
+    );
+}
+
+#[test]
+fn frontmatter_tags() {
+    assert_eq!(
+        fixture().frontmatter_tags().unwrap(),
+        set(&["journal", "project/alpha"])
+    );
+}
+
+#[test]
+fn inline_tags_skip_code_blocks() {
+    assert_eq!(
+        fixture().inline_tags().unwrap(),
+        set(&["project/beta", "status/active"])
+    );
+}
+
+#[test]
+fn single_pass_matches_separate_passes() {
+    use obsidian_get_tags::{collect_tags, remove_hash, ScanOptions};
+
+    let vault = fixture();
+    let options = ScanOptions {
+        inline: true,
+        ..Default::default()
+    };
+    let merged: HashSet<String> = collect_tags(&vault.note_paths().unwrap(), options)
+        .unwrap()
+        .iter()
+        .map(|tag| remove_hash(tag).to_string())
+        .collect();
+
+    let mut separate = vault.frontmatter_tags().unwrap();
+    separate.extend(vault.inline_tags().unwrap());
+    assert_eq!(merged, separate);
+}
+
+#[test]
+fn properties_with_and_without_delimiters() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/properties");
+    let tags = |name: &str, legacy_props| load_tags_with(&dir.join(name), legacy_props).unwrap();
+
+    assert_eq!(tags("delimited.md", false), ["delimited"]);
+    assert_eq!(tags("delimited.md", true), ["delimited"]);
+    assert!(tags("undelimited.md", false).is_empty());
+    assert_eq!(
+        tags("undelimited.md", true),
+        ["undelimited", "export/obsidian"]
+    );
+    // a leading `Key: value` sentence parses, but yields no tags
+    assert!(tags("markdown.md", true).is_empty());
+}
+
+#[test]
+fn missing_vault_is_an_error() {
+    let vault = Vault::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/missing"));
+
+    assert!(matches!(vault.note_paths(), Err(VaultError::NotFound(_))));
+}
+
+#[test]
+fn hashed_front_matter_tags_lose_their_hash() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/properties/hashed.md");
+
+    assert_eq!(load_tags_with(&path, false).unwrap(), ["foo", "bar", "baz"]);
+    assert_eq!(obsidian_get_tags::load_aliases(&path).unwrap(), ["#1 idea"]);
+}